@@ -3,4 +3,151 @@ CREATE TABLE IF NOT EXISTS services (
     name text NOT NULL UNIQUE,
     enable boolean,
     creation_time DATETIME DEFAULT CURRENT_TIMESTAMP
-);
\ No newline at end of file
+);
+
+CREATE TABLE IF NOT EXISTS products (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    name text NOT NULL UNIQUE,
+    url text NOT NULL,
+    retailer text NOT NULL,
+    currency text NOT NULL DEFAULT 'EUR',
+    creation_time DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT OR IGNORE INTO products (name, url, retailer) VALUES (
+    'mimosa_milk',
+    'https://www.continente.pt/produto/leite-proteina-sem-lactose-mimosa-7652960.html',
+    'continente'
+);
+
+CREATE TABLE IF NOT EXISTS price_observations (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    product_id integer NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+    timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+    price_cents integer NOT NULL,
+    currency text NOT NULL,
+    source text NOT NULL,
+    unit_price_cents integer,
+    unit text,
+    pack_quantity real,
+    pack_unit text,
+    regular_price_cents integer,
+    promo_label text,
+    promo_valid_from DATE,
+    promo_valid_until DATE
+);
+
+CREATE INDEX IF NOT EXISTS price_observations_product_timestamp
+    ON price_observations (product_id, timestamp);
+
+CREATE TABLE IF NOT EXISTS alerts (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    product_id integer NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+    kind text NOT NULL,
+    threshold_cents integer,
+    percentage real,
+    basis text NOT NULL DEFAULT 'pack',
+    currency text NOT NULL DEFAULT 'EUR',
+    creation_time DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS subscriptions (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    chat_id integer NOT NULL,
+    service_id integer NOT NULL REFERENCES services (id) ON DELETE CASCADE,
+    creation_time DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (chat_id, service_id)
+);
+
+CREATE TABLE IF NOT EXISTS users (
+    user_id integer PRIMARY KEY,
+    role text NOT NULL,
+    currency text,
+    creation_time DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS schedules (
+    service_id integer PRIMARY KEY REFERENCES services (id) ON DELETE CASCADE,
+    spec text NOT NULL,
+    update_time DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS service_runs (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    service_id integer NOT NULL REFERENCES services (id) ON DELETE CASCADE,
+    started_at DATETIME NOT NULL,
+    finished_at DATETIME NOT NULL,
+    success boolean NOT NULL,
+    error text
+);
+
+CREATE INDEX IF NOT EXISTS service_runs_service_started
+    ON service_runs (service_id, started_at);
+
+CREATE TABLE IF NOT EXISTS stock_events (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    product_id integer NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+    timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+    availability text NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS exchange_rates (
+    currency text PRIMARY KEY,
+    rate real NOT NULL,
+    update_time DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS product_groups (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    name text NOT NULL UNIQUE,
+    cheapest_product_id integer REFERENCES products (id) ON DELETE SET NULL,
+    creation_time DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS product_group_members (
+    group_id integer NOT NULL REFERENCES product_groups (id) ON DELETE CASCADE,
+    product_id integer NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+    PRIMARY KEY (group_id, product_id)
+);
+
+CREATE TABLE IF NOT EXISTS scrapers (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    name text NOT NULL UNIQUE,
+    url_pattern text NOT NULL,
+    price_path text NOT NULL,
+    price_regex text,
+    decimal_separator text NOT NULL DEFAULT ',',
+    currency text NOT NULL DEFAULT 'EUR',
+    unit_price_path text,
+    pack_size_path text,
+    regular_price_path text,
+    promo_label_path text,
+    out_of_stock_path text,
+    creation_time DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS http_cache (
+    url text PRIMARY KEY,
+    etag text,
+    last_modified text,
+    content_hash text NOT NULL,
+    update_time DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS scraper_health (
+    product_id integer PRIMARY KEY REFERENCES products (id) ON DELETE CASCADE,
+    consecutive_failures integer NOT NULL DEFAULT 0,
+    failure_kind text,
+    last_error text,
+    failing_html text,
+    update_time DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS page_snapshots (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    product_id integer NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+    url text NOT NULL,
+    fetch_time DATETIME DEFAULT CURRENT_TIMESTAMP,
+    outcome text NOT NULL,
+    html blob NOT NULL
+);
@@ -0,0 +1,441 @@
+use crate::db;
+use crate::exchange_rates::ExchangeRates;
+use crate::milk_price::PriceSnapshot;
+use crate::money::{Currency, Money};
+use crate::units::Unit;
+use sqlx::FromRow;
+use std::fmt;
+
+/// Condition a price change has to meet before subscribers get notified.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlertRule {
+    /// Price goes from at or above the threshold to below it.
+    Below(Money),
+    /// Price goes from at or below the threshold to above it.
+    Above(Money),
+    /// Price drops by at least this percentage in a single change.
+    Drop(f32),
+}
+
+impl AlertRule {
+    /// Parses rules such as `below 1.10`, `above 2` or `drop 10%`, prices being in `currency`.
+    pub fn parse(text: &str, currency: Currency) -> Option<Self> {
+        let mut words = text.split_whitespace();
+        let kind = words.next()?;
+        let value = words.next()?;
+        if words.next().is_some() {
+            return None;
+        }
+        let price = || Money::parse(value, currency).filter(|price| price.cents > 0);
+        match kind.to_lowercase().as_str() {
+            "below" => Some(Self::Below(price()?)),
+            "above" => Some(Self::Above(price()?)),
+            "drop" => {
+                let percentage = value
+                    .trim_end_matches('%')
+                    .replace(',', ".")
+                    .parse::<f32>()
+                    .ok()?;
+                (percentage.is_finite() && percentage > 0.0).then_some(Self::Drop(percentage))
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads back a rule stored with [`Self::kind`], [`Self::threshold_cents`],
+    /// [`Self::percentage`] and [`Self::currency`].
+    fn from_stored(
+        kind: &str,
+        threshold_cents: Option<i64>,
+        percentage: Option<f64>,
+        currency: Currency,
+    ) -> Option<Self> {
+        let price = || {
+            threshold_cents
+                .filter(|cents| *cents > 0)
+                .map(|cents| Money::new(cents, currency))
+        };
+        match kind {
+            "below" => Some(Self::Below(price()?)),
+            "above" => Some(Self::Above(price()?)),
+            "drop" => percentage
+                .filter(|percentage| percentage.is_finite() && *percentage > 0.0)
+                .map(|percentage| Self::Drop(percentage as f32)),
+            _ => None,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Below(_) => "below",
+            Self::Above(_) => "above",
+            Self::Drop(_) => "drop",
+        }
+    }
+
+    /// Price thresholds are stored in cents, `None` for percentages.
+    fn threshold_cents(&self) -> Option<i64> {
+        match self {
+            Self::Below(price) | Self::Above(price) => Some(price.cents),
+            Self::Drop(_) => None,
+        }
+    }
+
+    fn percentage(&self) -> Option<f64> {
+        match self {
+            Self::Below(_) | Self::Above(_) => None,
+            Self::Drop(percentage) => Some(*percentage as f64),
+        }
+    }
+
+    /// Currency of the threshold, `None` for percentages which apply to any currency.
+    fn currency(&self) -> Option<Currency> {
+        match self {
+            Self::Below(price) | Self::Above(price) => Some(price.currency),
+            Self::Drop(_) => None,
+        }
+    }
+
+    /// Whether the change from `previous` to `current` crosses this rule's boundary.
+    pub fn is_triggered(&self, previous: Money, current: Money) -> bool {
+        match *self {
+            Self::Below(threshold) => previous >= threshold && current < threshold,
+            Self::Above(threshold) => previous <= threshold && current > threshold,
+            Self::Drop(percentage) => {
+                previous.cents > 0
+                    && current.currency == previous.currency
+                    && (previous.cents - current.cents) as f64 / previous.cents as f64 * 100.0
+                        >= percentage as f64
+            }
+        }
+    }
+}
+
+impl fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Below(value) => write!(f, "below {}", value),
+            Self::Above(value) => write!(f, "above {}", value),
+            Self::Drop(value) => write!(f, "drop of {}%", value),
+        }
+    }
+}
+
+/// Which price of a product an alert watches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PriceBasis {
+    /// The shelf price of the pack.
+    Pack,
+    /// The price per litre, kilogram or piece, comparable across pack sizes.
+    Unit,
+}
+
+impl PriceBasis {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pack => "pack",
+            Self::Unit => "unit",
+        }
+    }
+
+    fn from_stored(text: &str) -> Option<Self> {
+        match text {
+            "pack" => Some(Self::Pack),
+            "unit" => Some(Self::Unit),
+            _ => None,
+        }
+    }
+
+    /// The price of `snapshot` this basis refers to, `None` when the page had no unit price.
+    pub fn price_of(&self, snapshot: &PriceSnapshot) -> Option<Money> {
+        match self {
+            Self::Pack => Some(snapshot.price),
+            Self::Unit => snapshot.unit_price.map(|unit_price| unit_price.price),
+        }
+    }
+}
+
+/// Parses an alert rule whose value may end in a unit, e.g. `below 0.90/l`, which makes the
+/// alert watch the unit price instead of the pack price.
+pub fn parse_alert(text: &str, currency: Currency) -> Option<(AlertRule, PriceBasis)> {
+    match text.trim_end().rsplit_once('/') {
+        Some((rule, unit)) if unit.eq_ignore_ascii_case("unit") || Unit::parse(unit).is_some() => {
+            Some((AlertRule::parse(rule, currency)?, PriceBasis::Unit))
+        }
+        Some(_) => None,
+        None => Some((AlertRule::parse(text, currency)?, PriceBasis::Pack)),
+    }
+}
+
+#[derive(Clone, FromRow, Debug)]
+struct AlertSchema {
+    id: i64,
+    product_id: i64,
+    kind: String,
+    threshold_cents: Option<i64>,
+    percentage: Option<f64>,
+    basis: String,
+    currency: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Alert {
+    pub id: i64,
+    pub product_id: i64,
+    pub rule: AlertRule,
+    pub basis: PriceBasis,
+}
+
+impl Alert {
+    /// Whether the change between the snapshots crosses the rule on this alert's basis. Prices
+    /// are converted with `rates` to the threshold currency first, so an alert set in another
+    /// currency than the product's still triggers.
+    pub fn is_triggered(
+        &self,
+        previous: &PriceSnapshot,
+        current: &PriceSnapshot,
+        rates: &ExchangeRates,
+    ) -> bool {
+        let (Some(previous), Some(current)) =
+            (self.basis.price_of(previous), self.basis.price_of(current))
+        else {
+            return false;
+        };
+        let currency = self.rule.currency().unwrap_or(previous.currency);
+        match (
+            rates.convert(previous, currency),
+            rates.convert(current, currency),
+        ) {
+            (Some(previous), Some(current)) => self.rule.is_triggered(previous, current),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.basis {
+            PriceBasis::Pack => write!(f, "{}", self.rule),
+            PriceBasis::Unit => write!(f, "{} per unit", self.rule),
+        }
+    }
+}
+
+impl TryFrom<AlertSchema> for Alert {
+    type Error = String;
+
+    fn try_from(schema: AlertSchema) -> Result<Self, Self::Error> {
+        let currency = Currency::parse(&schema.currency)
+            .ok_or_else(|| format!("invalid alert currency '{}'", schema.currency))?;
+        let rule = AlertRule::from_stored(
+            &schema.kind,
+            schema.threshold_cents,
+            schema.percentage,
+            currency,
+        )
+        .ok_or_else(|| format!("invalid alert rule '{}'", schema.kind))?;
+        let basis = PriceBasis::from_stored(&schema.basis)
+            .ok_or_else(|| format!("invalid alert basis '{}'", schema.basis))?;
+        Ok(Self {
+            id: schema.id,
+            product_id: schema.product_id,
+            rule,
+            basis,
+        })
+    }
+}
+
+pub async fn add_alert(
+    product_id: i64,
+    rule: AlertRule,
+    basis: PriceBasis,
+) -> Result<i64, sqlx::Error> {
+    let db = db::get_db().await;
+    let insert_result = sqlx::query(
+        "INSERT INTO alerts (product_id, kind, threshold_cents, percentage, basis, currency)
+         VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(product_id)
+    .bind(rule.kind())
+    .bind(rule.threshold_cents())
+    .bind(rule.percentage())
+    .bind(basis.as_str())
+    .bind(rule.currency().unwrap_or(Currency::EUR).as_str())
+    .execute(&db)
+    .await?;
+    Ok(insert_result.last_insert_rowid())
+}
+
+/// Deletes the alert with `id`, returning whether it existed.
+pub async fn remove_alert(id: i64) -> Result<bool, sqlx::Error> {
+    let db = db::get_db().await;
+    let delete_result = sqlx::query("DELETE FROM alerts WHERE id = ?")
+        .bind(id)
+        .execute(&db)
+        .await?;
+    Ok(delete_result.rows_affected() > 0)
+}
+
+pub async fn get_alerts(product_id: i64) -> Vec<Alert> {
+    let db = db::get_db().await;
+    let alerts_query = sqlx::query_as::<_, AlertSchema>(
+        "SELECT id, product_id, kind, threshold_cents, percentage, basis, currency FROM alerts
+         WHERE product_id = ? ORDER BY id",
+    )
+    .bind(product_id)
+    .fetch_all(&db)
+    .await;
+
+    match alerts_query {
+        Ok(alerts) => alerts
+            .into_iter()
+            .filter_map(|schema| match Alert::try_from(schema) {
+                Ok(alert) => Some(alert),
+                Err(err) => {
+                    log::error!("Skipping alert of product {}: {}", product_id, err);
+                    None
+                }
+            })
+            .collect(),
+        Err(err) => {
+            log::error!(
+                "Failed to query alerts of product {}! {:?}",
+                product_id,
+                err
+            );
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::UnitPrice;
+
+    fn parse_rule(text: &str) -> Option<AlertRule> {
+        AlertRule::parse(text, Currency::EUR)
+    }
+
+    fn parse_eur_alert(text: &str) -> Option<(AlertRule, PriceBasis)> {
+        parse_alert(text, Currency::EUR)
+    }
+
+    #[test]
+    fn test_stored_rule() {
+        for text in ["below 1.10", "above 0,29", "drop 12.5%"] {
+            let rule = parse_rule(text).unwrap();
+            let stored = AlertRule::from_stored(
+                rule.kind(),
+                rule.threshold_cents(),
+                rule.percentage(),
+                Currency::EUR,
+            );
+            assert_eq!(stored, Some(rule));
+        }
+        assert_eq!(
+            parse_rule("above 0,29").unwrap().threshold_cents(),
+            Some(29)
+        );
+        assert_eq!(
+            AlertRule::from_stored("below", None, Some(10.0), Currency::EUR),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        assert_eq!(
+            parse_rule("below 1.10"),
+            Some(AlertRule::Below(Money::eur(110)))
+        );
+        assert_eq!(
+            parse_rule("ABOVE 2,5"),
+            Some(AlertRule::Above(Money::eur(250)))
+        );
+        assert_eq!(parse_rule("drop 10%"), Some(AlertRule::Drop(10.0)));
+        assert_eq!(parse_rule("below"), None);
+        assert_eq!(parse_rule("below -1"), None);
+        assert_eq!(parse_rule("sideways 1"), None);
+        assert_eq!(parse_rule("below 1 2"), None);
+    }
+
+    #[test]
+    fn test_parse_unit_basis() {
+        assert_eq!(
+            parse_eur_alert("below 0.90/l"),
+            Some((AlertRule::Below(Money::eur(90)), PriceBasis::Unit))
+        );
+        assert_eq!(
+            parse_eur_alert("drop 10%/unit"),
+            Some((AlertRule::Drop(10.0), PriceBasis::Unit))
+        );
+        assert_eq!(
+            parse_eur_alert("above 2"),
+            Some((AlertRule::Above(Money::eur(200)), PriceBasis::Pack))
+        );
+        assert_eq!(parse_eur_alert("below 0.90/box"), None);
+    }
+
+    #[test]
+    fn test_unit_basis_triggering() {
+        let alert = Alert {
+            id: 1,
+            product_id: 1,
+            rule: AlertRule::Below(Money::eur(100)),
+            basis: PriceBasis::Unit,
+        };
+        let per_liter = |cents, liters| {
+            let mut snapshot = PriceSnapshot::new(Money::eur(cents));
+            snapshot.unit_price = Some(UnitPrice {
+                price: Money::eur(cents).divide(liters),
+                unit: Unit::Liter,
+            });
+            snapshot
+        };
+        // A bigger pack costs more but crosses the per litre boundary.
+        let rates = ExchangeRates::default();
+        assert!(alert.is_triggered(&per_liter(105, 1.0), &per_liter(570, 6.0), &rates));
+        assert!(!alert.is_triggered(
+            &per_liter(105, 1.0),
+            &PriceSnapshot::new(Money::eur(50)),
+            &rates
+        ));
+    }
+
+    #[test]
+    fn test_threshold_crossing() {
+        let below = AlertRule::Below(Money::eur(110));
+        assert!(below.is_triggered(Money::eur(120), Money::eur(105)));
+        assert!(!below.is_triggered(Money::eur(105), Money::eur(100)));
+        assert!(!below.is_triggered(Money::eur(130), Money::eur(120)));
+
+        let above = AlertRule::Above(Money::eur(150));
+        assert!(above.is_triggered(Money::eur(140), Money::eur(160)));
+        assert!(!above.is_triggered(Money::eur(160), Money::eur(170)));
+    }
+
+    #[test]
+    fn test_percentage_drop() {
+        let drop = AlertRule::Drop(10.0);
+        assert!(drop.is_triggered(Money::eur(200), Money::eur(180)));
+        assert!(!drop.is_triggered(Money::eur(200), Money::eur(190)));
+        assert!(!drop.is_triggered(Money::eur(200), Money::eur(220)));
+    }
+
+    #[test]
+    fn test_converted_triggering() {
+        let usd = Currency::parse("USD").unwrap();
+        let rates = ExchangeRates::new([(usd, 1.25)]);
+        let alert = Alert {
+            id: 1,
+            product_id: 1,
+            rule: AlertRule::parse("below 1.50", usd).unwrap(),
+            basis: PriceBasis::Pack,
+        };
+        let eur = |cents| PriceSnapshot::new(Money::eur(cents));
+        // 1,25 € is 1.56 US$ and 1,15 € is 1.44 US$.
+        assert!(alert.is_triggered(&eur(125), &eur(115), &rates));
+        assert!(!alert.is_triggered(&eur(125), &eur(115), &ExchangeRates::default()));
+    }
+}
@@ -0,0 +1,212 @@
+use crate::money::{Currency, Money};
+use crate::price_history::PriceObservation;
+use crate::scheduler::DEFAULT_TIMEZONE;
+use chrono::{DateTime, NaiveDateTime};
+use plotters::prelude::*;
+use std::sync::Once;
+use thiserror::Error;
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 450;
+/// Labels on each axis, plotters picks round values near them.
+const X_LABELS: usize = 6;
+const Y_LABELS: usize = 5;
+const LINE_COLOR: RGBColor = RGBColor(33, 150, 243);
+const GRID_COLOR: RGBColor = RGBColor(224, 224, 224);
+const LIGHT_GRID_COLOR: RGBColor = RGBColor(244, 244, 244);
+/// Bundled so labels render the same without fonts installed on the host.
+const FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+
+#[derive(Error, Debug)]
+pub enum ChartError {
+    #[error("No prices to draw")]
+    NoData,
+    #[error("Failed to draw chart: {0}")]
+    Drawing(String),
+    #[error(transparent)]
+    Encoding(#[from] png::EncodingError),
+}
+
+/// Renders the observations as a stepped line chart with date and price axes and returns it
+/// encoded as PNG. Prices are labelled in the currency of the first observation.
+pub fn render_price_chart(observations: &[PriceObservation]) -> Result<Vec<u8>, ChartError> {
+    let points: Vec<(f64, f64)> = observations
+        .iter()
+        .filter_map(|observation| {
+            let time =
+                NaiveDateTime::parse_from_str(&observation.timestamp, "%Y-%m-%d %H:%M:%S").ok()?;
+            Some((
+                time.and_utc().timestamp() as f64,
+                observation.price().to_f64(),
+            ))
+        })
+        .collect();
+    if points.is_empty() {
+        return Err(ChartError::NoData);
+    }
+
+    let currency = observations[0].price().currency;
+    let mut buffer = vec![0; (WIDTH * HEIGHT * 3) as usize];
+    draw(&points, currency, &mut buffer).map_err(|err| ChartError::Drawing(err.to_string()))?;
+    encode_png(&buffer)
+}
+
+fn register_font() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        if plotters::style::register_font("sans-serif", FontStyle::Normal, FONT).is_err() {
+            log::error!("Failed to load the chart font!");
+        }
+    });
+}
+
+/// Date label of a timestamp in seconds, with the time when the chart spans less than 2 days.
+fn format_date(timestamp: f64, span: f64) -> String {
+    let Some(time) = DateTime::from_timestamp(timestamp as i64, 0) else {
+        return String::new();
+    };
+    let format = if span < 2.0 * 86400.0 {
+        "%d/%m %H:%M"
+    } else {
+        "%d/%m/%y"
+    };
+    time.with_timezone(&DEFAULT_TIMEZONE)
+        .format(format)
+        .to_string()
+}
+
+fn draw(
+    points: &[(f64, f64)],
+    currency: Currency,
+    buffer: &mut [u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    register_font();
+    let (x_range, y_range) = ranges(points);
+    let span = x_range.end - x_range.start;
+    let root = BitMapBackend::with_buffer(buffer, (WIDTH, HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let root = root.margin(20, 20, 20, 30);
+    let mut chart = ChartBuilder::on(&root)
+        .x_label_area_size(30)
+        .y_label_area_size(70)
+        .build_cartesian_2d(x_range, y_range)?;
+
+    chart
+        .configure_mesh()
+        .light_line_style(LIGHT_GRID_COLOR)
+        .bold_line_style(GRID_COLOR)
+        .x_labels(X_LABELS)
+        .y_labels(Y_LABELS)
+        .x_label_formatter(&|x| format_date(*x, span))
+        .y_label_formatter(&|y| Money::from_f64(*y, currency).to_string())
+        .label_style(("sans-serif", 14))
+        .draw()?;
+
+    // Prices hold until the next observation, so draw steps rather than slopes.
+    let mut steps = vec![];
+    for window in points.windows(2) {
+        steps.push(window[0]);
+        steps.push((window[1].0, window[0].1));
+    }
+    steps.extend(points.last());
+    chart.draw_series(LineSeries::new(steps, LINE_COLOR.stroke_width(3)))?;
+    chart.draw_series(
+        points
+            .iter()
+            .map(|point| Circle::new(*point, 3, LINE_COLOR.filled())),
+    )?;
+
+    root.present()?;
+    Ok(())
+}
+
+/// Axis ranges padded so flat histories and single observations are still visible.
+fn ranges(points: &[(f64, f64)]) -> (std::ops::Range<f64>, std::ops::Range<f64>) {
+    let (mut x_min, mut x_max) = (f64::INFINITY, f64::NEG_INFINITY);
+    let (mut y_min, mut y_max) = (f64::INFINITY, f64::NEG_INFINITY);
+    for (x, y) in points {
+        x_min = x_min.min(*x);
+        x_max = x_max.max(*x);
+        y_min = y_min.min(*y);
+        y_max = y_max.max(*y);
+    }
+    if x_max - x_min < 1.0 {
+        x_min -= 3600.0;
+        x_max += 3600.0;
+    }
+    let y_padding = ((y_max - y_min) * 0.1).max(0.05);
+    (
+        x_min..x_max,
+        (y_min - y_padding).max(0.0)..y_max + y_padding,
+    )
+}
+
+fn encode_png(buffer: &[u8]) -> Result<Vec<u8>, ChartError> {
+    let mut png_bytes = vec![];
+    {
+        let mut encoder = png::Encoder::new(&mut png_bytes, WIDTH, HEIGHT);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(buffer)?;
+    }
+    Ok(png_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history;
+    use crate::price_history;
+
+    #[test]
+    fn test_render_price_chart() {
+        let observations = vec![
+            PriceObservation::test("2024-06-01 10:00:00", 129),
+            PriceObservation::test("2024-06-02 10:00:00", 119),
+            PriceObservation::test("2024-06-03 10:00:00", 139),
+        ];
+        let png_bytes = render_price_chart(&observations).unwrap();
+
+        let decoder = png::Decoder::new(png_bytes.as_slice());
+        let reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().width, WIDTH);
+        assert_eq!(reader.info().height, HEIGHT);
+    }
+
+    #[test]
+    fn test_render_single_observation() {
+        let observations = vec![PriceObservation::test("2024-06-01 10:00:00", 129)];
+        assert!(render_price_chart(&observations).is_ok());
+    }
+
+    #[test]
+    fn test_format_date() {
+        let time = NaiveDateTime::parse_from_str("2024-06-01 10:00:00", "%Y-%m-%d %H:%M:%S")
+            .unwrap()
+            .and_utc()
+            .timestamp() as f64;
+        assert_eq!(format_date(time, 30.0 * 86400.0), "01/06/24");
+        assert_eq!(format_date(time, 3600.0), "01/06 11:00");
+    }
+
+    #[test]
+    fn test_render_without_observations() {
+        assert!(matches!(render_price_chart(&[]), Err(ChartError::NoData)));
+    }
+
+    #[tokio::test]
+    async fn test_render_all_period() {
+        let db = price_history::test_db(&[
+            PriceObservation::test("2019-03-01 10:00:00", 99),
+            PriceObservation::test("2022-08-15 10:00:00", 119),
+            PriceObservation::test("2024-06-01 10:00:00", 129),
+        ])
+        .await;
+        let days = history::parse_period_days("all").unwrap();
+        let observations =
+            price_history::query_observations(&db, 1, days.map(|days| days * 24 * 60 * 60)).await;
+        assert_eq!(observations.len(), 3);
+        assert!(render_price_chart(&observations).is_ok());
+    }
+}
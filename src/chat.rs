@@ -1,11 +1,87 @@
-use crate::constants::JMARCELOMB_RECIPIENT;
+use crate::constants;
+use crate::subscriptions;
+use crate::users;
 use teloxide::prelude::*;
+use teloxide::types::InputFile;
 use teloxide::RequestError;
 
-pub async fn send_message(msg: &str) -> Result<Message, RequestError> {
-    log::info!("Sending message: {}", msg);
+pub async fn send_message(chat_id: ChatId, msg: &str) -> Result<Message, RequestError> {
+    log::info!("Sending message to {}: {}", chat_id, msg);
     let bot = Bot::from_env();
-    let message = bot.send_message(JMARCELOMB_RECIPIENT.clone(), msg).await?;
+    let message = bot.send_message(chat_id, msg).await?;
 
     Ok(message)
 }
+
+/// Private chat of the bootstrapped admin, who gets the messages of services nobody
+/// subscribed to as they did before subscriptions existed.
+fn fallback_chat() -> ChatId {
+    ChatId(constants::ADMIN_USER_ID.0 as i64)
+}
+
+/// Sends `msg` to every chat subscribed to the service with `service_name`, or to the admin
+/// when there are none.
+pub async fn notify_subscribers(service_name: &str, msg: &str) {
+    let mut subscribers = subscriptions::get_subscribers(service_name).await;
+    if subscribers.is_empty() {
+        log::info!("No subscribers for '{}', notifying the admin", service_name);
+        subscribers.push(fallback_chat());
+    }
+
+    for chat_id in subscribers {
+        if let Err(err) = send_message(chat_id, msg).await {
+            log::error!("Failed to notify chat {}: {}", chat_id, err);
+        }
+    }
+}
+
+/// Sends `msg` once to every chat subscribed to any of the services in `service_names`, or to
+/// the admin when there are none.
+pub async fn notify_subscribers_of(service_names: &[&str], msg: &str) {
+    let mut subscribers: Vec<ChatId> = vec![];
+    for service_name in service_names {
+        for chat_id in subscriptions::get_subscribers(service_name).await {
+            if !subscribers.contains(&chat_id) {
+                subscribers.push(chat_id);
+            }
+        }
+    }
+    if subscribers.is_empty() {
+        log::info!(
+            "No subscribers for {:?}, notifying the admin",
+            service_names
+        );
+        subscribers.push(fallback_chat());
+    }
+
+    for chat_id in subscribers {
+        if let Err(err) = send_message(chat_id, msg).await {
+            log::error!("Failed to notify chat {}: {}", chat_id, err);
+        }
+    }
+}
+
+/// Sends `msg` to the private chat of every admin.
+pub async fn notify_admins(msg: &str) {
+    for user_id in users::get_admins().await {
+        if let Err(err) = send_message(ChatId(user_id.0 as i64), msg).await {
+            log::error!("Failed to notify admin {}: {}", user_id, err);
+        }
+    }
+}
+
+/// Sends `msg` to the private chat of every admin, followed by `contents` as a file.
+pub async fn notify_admins_with_file(msg: &str, file_name: &str, contents: &[u8]) {
+    let bot = Bot::from_env();
+    for user_id in users::get_admins().await {
+        let chat_id = ChatId(user_id.0 as i64);
+        let file = InputFile::memory(contents.to_vec()).file_name(file_name.to_string());
+        let sent = match send_message(chat_id, msg).await {
+            Ok(_) => bot.send_document(chat_id, file).await.map(|_| ()),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = sent {
+            log::error!("Failed to notify admin {}: {}", user_id, err);
+        }
+    }
+}
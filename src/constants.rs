@@ -1,13 +1,31 @@
 use once_cell::sync::Lazy;
-use teloxide::types::{ChatId, Recipient};
+use teloxide::types::UserId;
 
-pub static JMARCELOMB_CHAT_ID: Lazy<ChatId> = Lazy::new(|| {
-    let chat_id: i64 = std::env::var("JMARCELOMB_CHAT_ID")
+/// User bootstrapped as admin at startup, `ADMIN_USER_ID` or else the owner's private chat.
+pub static ADMIN_USER_ID: Lazy<UserId> = Lazy::new(|| {
+    let user_id: u64 = std::env::var("ADMIN_USER_ID")
+        .or_else(|_| std::env::var("JMARCELOMB_CHAT_ID"))
         .ok()
         .unwrap()
         .parse()
         .unwrap();
-    ChatId(chat_id)
+    UserId(user_id)
 });
 
-pub static JMARCELOMB_RECIPIENT: Lazy<Recipient> = Lazy::new(|| Recipient::Id(*JMARCELOMB_CHAT_ID));
+/// ECB-style XML feed the exchange rates are refreshed from, `EXCHANGE_RATES_URL` or else the
+/// ECB daily reference rates.
+pub static EXCHANGE_RATES_URL: Lazy<String> = Lazy::new(|| {
+    std::env::var("EXCHANGE_RATES_URL").unwrap_or_else(|_| {
+        "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml".to_string()
+    })
+});
+
+/// Consecutive failed checks of a product before the admins are alerted,
+/// `SCRAPER_FAILURE_THRESHOLD` or else 3.
+pub static SCRAPER_FAILURE_THRESHOLD: Lazy<i64> = Lazy::new(|| {
+    std::env::var("SCRAPER_FAILURE_THRESHOLD")
+        .ok()
+        .and_then(|threshold| threshold.parse().ok())
+        .filter(|threshold| *threshold > 0)
+        .unwrap_or(3)
+});
@@ -2,6 +2,47 @@ use sqlx::{migrate::MigrateDatabase, Sqlite, SqlitePool};
 
 const DB_URL: &str = "sqlite://db/sqlite.db";
 
+/// Columns added to tables after they were first released. `CREATE TABLE IF NOT EXISTS` only
+/// covers new databases, so existing ones get these through `ALTER TABLE` on startup.
+const ADDED_COLUMNS: [(&str, &str, &str); 15] = [
+    ("price_observations", "price_cents", "integer"),
+    ("price_observations", "unit_price_cents", "integer"),
+    ("price_observations", "unit", "text"),
+    ("price_observations", "pack_quantity", "real"),
+    ("price_observations", "pack_unit", "text"),
+    ("price_observations", "regular_price_cents", "integer"),
+    ("price_observations", "promo_label", "text"),
+    ("price_observations", "promo_valid_from", "DATE"),
+    ("price_observations", "promo_valid_until", "DATE"),
+    ("alerts", "basis", "text NOT NULL DEFAULT 'pack'"),
+    ("alerts", "currency", "text NOT NULL DEFAULT 'EUR'"),
+    ("alerts", "threshold_cents", "integer"),
+    ("alerts", "percentage", "real"),
+    ("products", "currency", "text NOT NULL DEFAULT 'EUR'"),
+    ("users", "currency", "text"),
+];
+
+/// Columns replaced by one of another type, as `(table, old, new, rows)`: while `old` still
+/// exists, `new` is filled from it in the rows matching the `rows` condition and `old` is
+/// dropped. Prices went from euros in `real` columns to cents in `integer` ones.
+const CONVERTED_PRICE_COLUMNS: [(&str, &str, &str, &str); 4] = [
+    ("price_observations", "price", "price_cents", "TRUE"),
+    (
+        "price_observations",
+        "unit_price",
+        "unit_price_cents",
+        "TRUE",
+    ),
+    (
+        "price_observations",
+        "regular_price",
+        "regular_price_cents",
+        "TRUE",
+    ),
+    // Drop alerts kept a percentage there, moved by `move_alert_percentages`.
+    ("alerts", "threshold", "threshold_cents", "kind != 'drop'"),
+];
+
 pub async fn init() {
     if !Sqlite::database_exists(DB_URL).await.unwrap_or(false) {
         println!("Creating database {}", DB_URL);
@@ -20,9 +61,129 @@ pub async fn init() {
         .await
         .unwrap();
     println!("DB creation result: {:?}", result);
+
+    migrate(&db).await;
+}
+
+async fn migrate(db: &SqlitePool) {
+    add_missing_columns(db).await;
+    move_alert_percentages(db).await;
+    convert_price_columns(db).await;
+}
+
+async fn has_column(db: &SqlitePool, table: &str, column: &str) -> bool {
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+        .bind(table)
+        .fetch_all(db)
+        .await
+        .unwrap();
+    columns.iter().any(|name| name == column)
+}
+
+async fn add_missing_columns(db: &SqlitePool) {
+    for (table, column, definition) in ADDED_COLUMNS {
+        if has_column(db, table, column).await {
+            continue;
+        }
+
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        ))
+        .execute(db)
+        .await
+        .unwrap();
+        println!("Added column {}.{}", table, column);
+    }
+}
+
+/// Alerts stored both prices and percentages in `threshold` before prices moved to cents,
+/// the percentages of drop alerts go to their own column before it's converted.
+async fn move_alert_percentages(db: &SqlitePool) {
+    if !has_column(db, "alerts", "threshold").await {
+        return;
+    }
+    sqlx::query(
+        "UPDATE alerts SET percentage = threshold WHERE kind = 'drop' AND percentage IS NULL",
+    )
+    .execute(db)
+    .await
+    .unwrap();
+}
+
+async fn convert_price_columns(db: &SqlitePool) {
+    for (table, old, new, rows) in CONVERTED_PRICE_COLUMNS {
+        if !has_column(db, table, old).await {
+            continue;
+        }
+
+        sqlx::query(&format!(
+            "UPDATE {0} SET {1} = CAST(ROUND({2} * 100) AS INTEGER) WHERE {1} IS NULL AND {3}",
+            table, new, old, rows
+        ))
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query(&format!("ALTER TABLE {} DROP COLUMN {}", table, old))
+            .execute(db)
+            .await
+            .unwrap();
+        println!("Converted column {}.{} to {}", table, old, new);
+    }
 }
 
 pub async fn get_db() -> sqlx::Pool<Sqlite> {
     let db: sqlx::Pool<Sqlite> = SqlitePool::connect(DB_URL).await.unwrap();
     db
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn test_migrate_alert_thresholds() {
+        // One connection, each would open its own in-memory database.
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query(
+            "CREATE TABLE products (id integer PRIMARY KEY, name text, url text, retailer text);
+             CREATE TABLE alerts (
+                 id integer PRIMARY KEY AUTOINCREMENT,
+                 product_id integer NOT NULL REFERENCES products (id),
+                 kind text NOT NULL,
+                 threshold real NOT NULL
+             );
+             INSERT INTO products (id, name) VALUES (1, 'mimosa');
+             INSERT INTO alerts (product_id, kind, threshold)
+             VALUES (1, 'below', 1.1), (1, 'above', 0.29), (1, 'drop', 12.5);",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        sqlx::query(include_str!("../db/creation.sql"))
+            .execute(&db)
+            .await
+            .unwrap();
+
+        migrate(&db).await;
+        assert!(!has_column(&db, "alerts", "threshold").await);
+        let alerts: Vec<(String, Option<i64>, Option<f64>)> =
+            sqlx::query_as("SELECT kind, threshold_cents, percentage FROM alerts ORDER BY id")
+                .fetch_all(&db)
+                .await
+                .unwrap();
+        assert_eq!(
+            alerts,
+            vec![
+                ("below".to_string(), Some(110), None),
+                ("above".to_string(), Some(29), None),
+                ("drop".to_string(), None, Some(12.5)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use crate::constants;
+use crate::db;
+use crate::http;
+use crate::money::{Currency, Money};
+use crate::scheduler::Schedule;
+use crate::services::{BotService, ServiceContext};
+use chrono::Utc;
+use futures::future::BoxFuture;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+/// Matches `<Cube currency='USD' rate='1.0812'/>` entries of ECB-style feeds.
+static RATE_ENTRY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"<Cube\s+currency=['"]([A-Za-z]{3})['"]\s+rate=['"]([0-9.]+)['"]"#).unwrap()
+});
+
+/// The ECB publishes its reference rates around 16:00 CET on working days.
+const REFRESH_SCHEDULE: &str = "cron 0 17 * * Mon-Fri jitter 10m";
+
+#[derive(Error, Debug)]
+pub enum ExchangeRateError {
+    #[error("Request failed with status: {0}")]
+    RequestFailed(reqwest::StatusCode),
+    #[error("No exchange rates found in the feed")]
+    EmptyFeed,
+    #[error(transparent)]
+    ReqwestError(#[from] reqwest::Error),
+    #[error(transparent)]
+    DbError(#[from] sqlx::Error),
+}
+
+/// Units of each currency one euro buys, the euro being the base of ECB reference rates.
+#[derive(Clone, Debug, Default)]
+pub struct ExchangeRates {
+    per_euro: HashMap<Currency, f64>,
+}
+
+impl ExchangeRates {
+    pub fn new(rates: impl IntoIterator<Item = (Currency, f64)>) -> Self {
+        let mut per_euro: HashMap<Currency, f64> = rates
+            .into_iter()
+            .filter(|(_, rate)| rate.is_finite() && *rate > 0.0)
+            .collect();
+        per_euro.insert(Currency::EUR, 1.0);
+        Self { per_euro }
+    }
+
+    pub fn rate(&self, currency: Currency) -> Option<f64> {
+        match currency {
+            Currency::EUR => Some(1.0),
+            _ => self.per_euro.get(&currency).copied(),
+        }
+    }
+
+    /// `money` in `currency`, rounded to the cent, `None` when either rate is unknown.
+    pub fn convert(&self, money: Money, currency: Currency) -> Option<Money> {
+        if money.currency == currency {
+            return Some(money);
+        }
+        let amount = money.cents as f64 / self.rate(money.currency)? * self.rate(currency)?;
+        Some(Money::new(amount.round() as i64, currency))
+    }
+
+    /// Known currencies other than the euro, by code.
+    pub fn rates(&self) -> Vec<(Currency, f64)> {
+        let mut rates: Vec<(Currency, f64)> = self
+            .per_euro
+            .iter()
+            .filter(|(currency, _)| **currency != Currency::EUR)
+            .map(|(currency, rate)| (*currency, *rate))
+            .collect();
+        rates.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        rates
+    }
+}
+
+/// Reads the rates of an ECB-style XML feed such as
+/// <https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml>. Only the first rate of
+/// each currency is kept, so historical feeds give the most recent day.
+pub fn parse_feed(xml: &str) -> Vec<(Currency, f64)> {
+    let mut rates: Vec<(Currency, f64)> = vec![];
+    for captures in RATE_ENTRY.captures_iter(xml) {
+        let (Some(currency), Ok(rate)) = (Currency::parse(&captures[1]), captures[2].parse())
+        else {
+            continue;
+        };
+        if !rates.iter().any(|(known, _)| *known == currency) {
+            rates.push((currency, rate));
+        }
+    }
+    rates
+}
+
+pub async fn fetch_feed(url: &str) -> Result<Vec<(Currency, f64)>, ExchangeRateError> {
+    let response = http::client().get(url).await?;
+    if !response.status().is_success() {
+        return Err(ExchangeRateError::RequestFailed(response.status()));
+    }
+
+    let rates = parse_feed(&response.text().await?);
+    if rates.is_empty() {
+        return Err(ExchangeRateError::EmptyFeed);
+    }
+    Ok(rates)
+}
+
+/// Replaces the stored rates with the ones of the feed at `url`, returning how many were read.
+pub async fn refresh_rates(url: &str) -> Result<usize, ExchangeRateError> {
+    let rates = fetch_feed(url).await?;
+    for (currency, rate) in &rates {
+        set_rate(*currency, *rate).await?;
+    }
+    log::info!("Refreshed {} exchange rates from {}", rates.len(), url);
+    Ok(rates.len())
+}
+
+/// Stores how many units of `currency` one euro buys, overriding the feed until its next
+/// refresh.
+pub async fn set_rate(currency: Currency, rate: f64) -> Result<(), sqlx::Error> {
+    let db = db::get_db().await;
+    sqlx::query(
+        "INSERT INTO exchange_rates (currency, rate) VALUES (?, ?)
+         ON CONFLICT (currency) DO UPDATE
+         SET rate = excluded.rate, update_time = CURRENT_TIMESTAMP",
+    )
+    .bind(currency.as_str())
+    .bind(rate)
+    .execute(&db)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_rates() -> ExchangeRates {
+    let db = db::get_db().await;
+    let rates_query =
+        sqlx::query_as::<_, (String, f64)>("SELECT currency, rate FROM exchange_rates")
+            .fetch_all(&db)
+            .await;
+
+    match rates_query {
+        Ok(rates) => ExchangeRates::new(
+            rates
+                .into_iter()
+                .filter_map(|(code, rate)| Some((Currency::parse(&code)?, rate))),
+        ),
+        Err(err) => {
+            log::error!("Failed to query exchange rates! {:?}", err);
+            ExchangeRates::default()
+        }
+    }
+}
+
+/// Service that keeps the `exchange_rates` table up to date with the configured feed.
+pub struct ExchangeRatesService {
+    url: String,
+    last_error: Mutex<Option<String>>,
+}
+
+impl ExchangeRatesService {
+    pub fn new() -> Self {
+        Self {
+            url: constants::EXCHANGE_RATES_URL.clone(),
+            last_error: Mutex::new(None),
+        }
+    }
+
+    async fn refresh(&self) -> Result<usize, ExchangeRateError> {
+        let result = refresh_rates(&self.url).await;
+        *self.last_error.lock().unwrap() = result.as_ref().err().map(ToString::to_string);
+        result
+    }
+
+    async fn refresh_periodically(&self, ctx: ServiceContext) {
+        loop {
+            let started_at = Utc::now();
+            let result = self.refresh().await;
+            if let Err(err) = &result {
+                log::error!("Failed to refresh exchange rates: {}", err);
+            }
+            ctx.record_run(
+                started_at,
+                result.map(|_| ()).map_err(|err| err.to_string()),
+            )
+            .await;
+            ctx.wait_for_next_run().await;
+        }
+    }
+}
+
+impl Default for ExchangeRatesService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BotService for ExchangeRatesService {
+    fn name(&self) -> &str {
+        "exchange_rates"
+    }
+
+    fn description(&self) -> String {
+        format!("Refreshes exchange rates from {}", self.url)
+    }
+
+    fn run(self: Arc<Self>, ctx: ServiceContext) -> BoxFuture<'static, ()> {
+        Box::pin(async move { self.refresh_periodically(ctx).await })
+    }
+
+    fn schedule(&self) -> Option<Schedule> {
+        Some(Schedule::parse(REFRESH_SCHEDULE).expect("valid exchange rates schedule"))
+    }
+
+    fn on_command<'a>(&'a self, args: &'a str) -> BoxFuture<'a, Option<String>> {
+        Box::pin(async move {
+            if args.trim() != "refresh" {
+                return None;
+            }
+            Some(match self.refresh().await {
+                Ok(count) => format!("Refreshed {} exchange rates.", count),
+                Err(err) => format!("Failed to refresh exchange rates: {}", err),
+            })
+        })
+    }
+
+    fn status(&self) -> Vec<(String, String)> {
+        match &*self.last_error.lock().unwrap() {
+            Some(error) => vec![("last error".to_string(), error.clone())],
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+    <gesmes:subject>Reference rates</gesmes:subject>
+    <Cube>
+        <Cube time='2024-06-14'>
+            <Cube currency='USD' rate='1.0686'/>
+            <Cube currency='GBP' rate='0.84175'/>
+            <Cube currency='BRL' rate='5.7479'/>
+        </Cube>
+        <Cube time='2024-06-13'>
+            <Cube currency='USD' rate='1.0812'/>
+        </Cube>
+    </Cube>
+</gesmes:Envelope>"#;
+
+    fn currency(code: &str) -> Currency {
+        Currency::parse(code).unwrap()
+    }
+
+    #[test]
+    fn test_parse_feed() {
+        assert_eq!(
+            parse_feed(FEED),
+            vec![
+                (currency("USD"), 1.0686),
+                (currency("GBP"), 0.84175),
+                (currency("BRL"), 5.7479)
+            ]
+        );
+        assert_eq!(parse_feed("<html>Maintenance</html>"), vec![]);
+    }
+
+    #[test]
+    fn test_convert() {
+        let rates = ExchangeRates::new(parse_feed(FEED));
+        let usd = currency("USD");
+        let gbp = currency("GBP");
+        assert_eq!(
+            rates.convert(Money::eur(100), usd),
+            Some(Money::new(107, usd))
+        );
+        assert_eq!(
+            rates.convert(Money::new(107, usd), Currency::EUR),
+            Some(Money::eur(100))
+        );
+        assert_eq!(
+            rates.convert(Money::new(1000, gbp), usd),
+            Some(Money::new(1269, usd))
+        );
+        assert_eq!(
+            rates.convert(Money::eur(100), Currency::EUR),
+            Some(Money::eur(100))
+        );
+        assert_eq!(rates.convert(Money::eur(100), currency("JPY")), None);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_feed() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/eurofxref-daily.xml")
+            .with_status(200)
+            .with_body(FEED)
+            .create();
+
+        let url = format!("{}/eurofxref-daily.xml", server.url());
+        let rates = fetch_feed(&url).await.unwrap();
+        assert_eq!(rates.len(), 3);
+        mock.assert();
+
+        let empty_mock = server
+            .mock("GET", "/empty.xml")
+            .with_status(200)
+            .with_body("<Cube></Cube>")
+            .create();
+        let url = format!("{}/empty.xml", server.url());
+        assert!(matches!(
+            fetch_feed(&url).await,
+            Err(ExchangeRateError::EmptyFeed)
+        ));
+        empty_mock.assert();
+    }
+}
@@ -0,0 +1,443 @@
+use crate::alerts::PriceBasis;
+use crate::chat;
+use crate::db;
+use crate::exchange_rates::{self, ExchangeRates};
+use crate::milk_price::PriceSnapshot;
+use crate::money::{Currency, Money};
+use crate::price_history;
+use crate::products::Product;
+use crate::stock::{self, Availability};
+use sqlx::FromRow;
+
+/// Several tracked products that are the same item sold by different retailers.
+#[derive(Clone, FromRow, Debug)]
+pub struct ProductGroup {
+    pub id: i64,
+    pub name: String,
+    /// Product found cheapest by the last comparison, to notice when another one takes over.
+    pub cheapest_product_id: Option<i64>,
+    pub creation_time: String,
+}
+
+/// One member of a group with the price it's compared by.
+#[derive(Clone, Debug)]
+pub struct Offer {
+    pub product: Product,
+    pub snapshot: PriceSnapshot,
+    /// Price on the comparison basis, converted to the comparison currency.
+    pub price: Money,
+}
+
+/// Members of a group from cheapest to most expensive.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    /// Unit prices when every member has one in the same unit, pack prices otherwise.
+    pub basis: PriceBasis,
+    pub offers: Vec<Offer>,
+    /// Members without a price to compare: never checked, out of stock or in a currency
+    /// without a known rate.
+    pub missing: Vec<Product>,
+}
+
+impl Comparison {
+    pub fn cheapest(&self) -> Option<&Offer> {
+        self.offers.first()
+    }
+}
+
+/// Ranks the latest snapshot of each member of a group, converting prices to `currency`.
+/// Products without a snapshot or out of stock are left out of the ranking.
+pub fn compare(
+    members: Vec<(Product, Option<PriceSnapshot>)>,
+    rates: &ExchangeRates,
+    currency: Currency,
+) -> Comparison {
+    let snapshots = members
+        .iter()
+        .filter_map(|(_, snapshot)| snapshot.as_ref())
+        .filter(|snapshot| snapshot.availability == Availability::InStock);
+    let mut units = snapshots.map(|snapshot| snapshot.unit_price.map(|unit_price| unit_price.unit));
+    let basis = match units.next() {
+        Some(Some(unit)) if units.all(|other| other == Some(unit)) => PriceBasis::Unit,
+        _ => PriceBasis::Pack,
+    };
+
+    let mut offers = vec![];
+    let mut missing = vec![];
+    for (product, snapshot) in members {
+        let price = snapshot
+            .as_ref()
+            .filter(|snapshot| snapshot.availability == Availability::InStock)
+            .and_then(|snapshot| basis.price_of(snapshot))
+            .and_then(|price| rates.convert(price, currency));
+        match (snapshot, price) {
+            (Some(snapshot), Some(price)) => offers.push(Offer {
+                product,
+                snapshot,
+                price,
+            }),
+            _ => missing.push(product),
+        }
+    }
+    offers.sort_by_key(|offer| offer.price.cents);
+
+    Comparison {
+        basis,
+        offers,
+        missing,
+    }
+}
+
+pub fn format_comparison(name: &str, comparison: &Comparison) -> String {
+    let Some(cheapest) = comparison.cheapest() else {
+        return format!("No prices to compare for '{}' yet.", name);
+    };
+    let basis = match comparison.basis {
+        PriceBasis::Pack => "pack price",
+        PriceBasis::Unit => "unit price",
+    };
+    let mut lines = vec![format!(
+        "🏆 Cheapest {} by {}: {} at {}",
+        name, basis, cheapest.product.name, cheapest.product.retailer
+    )];
+    for (position, offer) in comparison.offers.iter().enumerate() {
+        let price = match offer.snapshot.unit_price {
+            Some(unit_price) if comparison.basis == PriceBasis::Unit => {
+                format!("{}/{}", offer.price, unit_price.unit)
+            }
+            _ => offer.price.to_string(),
+        };
+        // The page price only adds something when it's not the compared one.
+        let details = if offer.price == offer.snapshot.price {
+            String::new()
+        } else {
+            format!(" · {}", offer.snapshot)
+        };
+        lines.push(format!(
+            "{}. {} ({}): {}{}",
+            position + 1,
+            offer.product.name,
+            offer.product.retailer,
+            price,
+            details
+        ));
+    }
+    for product in &comparison.missing {
+        lines.push(format!(
+            "– {} ({}): no price available",
+            product.name, product.retailer
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Message announcing that `current` took over as the cheapest member of group `name`.
+pub fn cheapest_change_message(name: &str, previous: &Product, current: &Offer) -> String {
+    format!(
+        "🏆 {} at {} is now the cheapest {} at {}, ahead of {} at {}! 🛒",
+        current.product.name,
+        current.product.retailer,
+        name,
+        current.snapshot,
+        previous.name,
+        previous.retailer
+    )
+}
+
+/// Latest recorded snapshot of each product, marked out of stock when that's the last known
+/// availability.
+pub async fn latest_snapshots(products: Vec<Product>) -> Vec<(Product, Option<PriceSnapshot>)> {
+    let mut snapshots = vec![];
+    for product in products {
+        let mut snapshot = price_history::latest_observation(product.id)
+            .await
+            .map(|observation| observation.snapshot());
+        if let Some(snapshot) = &mut snapshot {
+            if let Some(availability) = stock::latest_availability(product.id).await {
+                snapshot.availability = availability;
+            }
+        }
+        snapshots.push((product, snapshot));
+    }
+    snapshots
+}
+
+/// Compares the groups of `product_id` again after one of its checks, and notifies the
+/// subscribers of every member when another member became the cheapest. Comparisons are done
+/// in euros, a tie keeps the previous cheapest.
+pub async fn check_cheapest(product_id: i64) {
+    let rates = exchange_rates::get_rates().await;
+    for group in get_groups_of(product_id).await {
+        let members = get_members(group.id).await;
+        let comparison = compare(
+            latest_snapshots(members.clone()).await,
+            &rates,
+            Currency::EUR,
+        );
+        let previous = group.cheapest_product_id.and_then(|previous_id| {
+            comparison
+                .offers
+                .iter()
+                .find(|offer| offer.product.id == previous_id)
+        });
+        let Some(cheapest) = comparison.cheapest() else {
+            continue;
+        };
+        if previous.is_some_and(|previous| previous.price == cheapest.price) {
+            continue;
+        }
+        if group.cheapest_product_id == Some(cheapest.product.id) {
+            continue;
+        }
+        set_cheapest(group.id, Some(cheapest.product.id)).await;
+
+        let previous = group
+            .cheapest_product_id
+            .and_then(|previous_id| members.iter().find(|member| member.id == previous_id));
+        if let Some(previous) = previous {
+            let message = cheapest_change_message(&group.name, previous, cheapest);
+            let services: Vec<&str> = members.iter().map(|member| member.name.as_str()).collect();
+            chat::notify_subscribers_of(&services, &message).await;
+        }
+    }
+}
+
+pub async fn get_groups() -> Vec<ProductGroup> {
+    let db = db::get_db().await;
+    let groups_query =
+        sqlx::query_as::<_, ProductGroup>("SELECT * FROM product_groups ORDER BY name")
+            .fetch_all(&db)
+            .await;
+
+    match groups_query {
+        Ok(groups) => groups,
+        Err(err) => {
+            log::error!("Failed to query product groups! {:?}", err);
+            Vec::new()
+        }
+    }
+}
+
+pub async fn get_group(name: &str) -> Option<ProductGroup> {
+    let db = db::get_db().await;
+    let group_query =
+        sqlx::query_as::<_, ProductGroup>("SELECT * FROM product_groups WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&db)
+            .await;
+
+    match group_query {
+        Ok(group) => group,
+        Err(err) => {
+            log::error!("Failed to query product group '{}'! {:?}", name, err);
+            None
+        }
+    }
+}
+
+/// Groups containing the product with `product_id`.
+pub async fn get_groups_of(product_id: i64) -> Vec<ProductGroup> {
+    let db = db::get_db().await;
+    let groups_query = sqlx::query_as::<_, ProductGroup>(
+        "SELECT product_groups.* FROM product_groups
+         JOIN product_group_members ON product_group_members.group_id = product_groups.id
+         WHERE product_group_members.product_id = ? ORDER BY product_groups.name",
+    )
+    .bind(product_id)
+    .fetch_all(&db)
+    .await;
+
+    match groups_query {
+        Ok(groups) => groups,
+        Err(err) => {
+            log::error!(
+                "Failed to query groups of product {}! {:?}",
+                product_id,
+                err
+            );
+            Vec::new()
+        }
+    }
+}
+
+pub async fn get_members(group_id: i64) -> Vec<Product> {
+    let db = db::get_db().await;
+    let members_query = sqlx::query_as::<_, Product>(
+        "SELECT products.* FROM products
+         JOIN product_group_members ON product_group_members.product_id = products.id
+         WHERE product_group_members.group_id = ? ORDER BY products.name",
+    )
+    .bind(group_id)
+    .fetch_all(&db)
+    .await;
+
+    match members_query {
+        Ok(products) => products,
+        Err(err) => {
+            log::error!("Failed to query members of group {}! {:?}", group_id, err);
+            Vec::new()
+        }
+    }
+}
+
+/// Adds `product_id` to the group `name`, creating the group when needed. Adding a member
+/// twice is a no-op.
+pub async fn add_member(name: &str, product_id: i64) -> Result<(), sqlx::Error> {
+    let db = db::get_db().await;
+    sqlx::query("INSERT OR IGNORE INTO product_groups (name) VALUES (?)")
+        .bind(name)
+        .execute(&db)
+        .await?;
+    sqlx::query(
+        "INSERT OR IGNORE INTO product_group_members (group_id, product_id)
+         SELECT id, ? FROM product_groups WHERE name = ?",
+    )
+    .bind(product_id)
+    .bind(name)
+    .execute(&db)
+    .await?;
+    Ok(())
+}
+
+/// Removes `product_id` from the group `name`, returning whether it was a member.
+pub async fn remove_member(name: &str, product_id: i64) -> Result<bool, sqlx::Error> {
+    let db = db::get_db().await;
+    let delete_result = sqlx::query(
+        "DELETE FROM product_group_members
+         WHERE product_id = ? AND group_id IN (SELECT id FROM product_groups WHERE name = ?)",
+    )
+    .bind(product_id)
+    .bind(name)
+    .execute(&db)
+    .await?;
+    Ok(delete_result.rows_affected() > 0)
+}
+
+/// Deletes the group `name` and its memberships, returning whether it existed.
+pub async fn remove_group(name: &str) -> Result<bool, sqlx::Error> {
+    let db = db::get_db().await;
+    sqlx::query(
+        "DELETE FROM product_group_members
+         WHERE group_id IN (SELECT id FROM product_groups WHERE name = ?)",
+    )
+    .bind(name)
+    .execute(&db)
+    .await?;
+    let delete_result = sqlx::query("DELETE FROM product_groups WHERE name = ?")
+        .bind(name)
+        .execute(&db)
+        .await?;
+    Ok(delete_result.rows_affected() > 0)
+}
+
+pub async fn set_cheapest(group_id: i64, product_id: Option<i64>) {
+    let db = db::get_db().await;
+    let update_result =
+        sqlx::query("UPDATE product_groups SET cheapest_product_id = ? WHERE id = ?")
+            .bind(product_id)
+            .bind(group_id)
+            .execute(&db)
+            .await;
+
+    if let Err(err) = update_result {
+        log::error!(
+            "Update of cheapest product of group {} failed! {:?}",
+            group_id,
+            err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Unit, UnitPrice};
+
+    fn product(id: i64, retailer: &str) -> Product {
+        Product {
+            id,
+            name: format!("milk_{}", retailer),
+            url: format!("https://{}.pt/milk", retailer),
+            retailer: retailer.to_string(),
+            currency: "EUR".to_string(),
+            creation_time: String::new(),
+        }
+    }
+
+    fn per_liter(cents: i64, liters: f32) -> PriceSnapshot {
+        let mut snapshot = PriceSnapshot::new(Money::eur(cents));
+        snapshot.unit_price = Some(UnitPrice {
+            price: Money::eur(cents).divide(liters),
+            unit: Unit::Liter,
+        });
+        snapshot
+    }
+
+    #[test]
+    fn test_compare_unit_prices() {
+        let mut out_of_stock = per_liter(80, 1.0);
+        out_of_stock.availability = Availability::OutOfStock;
+        let comparison = compare(
+            vec![
+                (product(1, "continente"), Some(per_liter(105, 1.0))),
+                (product(2, "pingodoce"), Some(per_liter(570, 6.0))),
+                (product(3, "auchan"), Some(out_of_stock)),
+                (product(4, "minipreco"), None),
+            ],
+            &ExchangeRates::default(),
+            Currency::EUR,
+        );
+
+        assert_eq!(comparison.basis, PriceBasis::Unit);
+        let ranking: Vec<(i64, Money)> = comparison
+            .offers
+            .iter()
+            .map(|offer| (offer.product.id, offer.price))
+            .collect();
+        assert_eq!(ranking, vec![(2, Money::eur(95)), (1, Money::eur(105))]);
+        let missing: Vec<i64> = comparison.missing.iter().map(|p| p.id).collect();
+        assert_eq!(missing, vec![3, 4]);
+    }
+
+    #[test]
+    fn test_compare_pack_prices() {
+        let usd = Currency::parse("USD").unwrap();
+        let rates = ExchangeRates::new([(usd, 1.25)]);
+        let comparison = compare(
+            vec![
+                (product(1, "continente"), Some(per_liter(105, 1.0))),
+                (
+                    product(2, "walmart"),
+                    Some(PriceSnapshot::new(Money::new(125, usd))),
+                ),
+            ],
+            &rates,
+            Currency::EUR,
+        );
+
+        // Without a unit price for every member, pack prices are compared.
+        assert_eq!(comparison.basis, PriceBasis::Pack);
+        assert_eq!(comparison.cheapest().unwrap().product.id, 2);
+        assert_eq!(comparison.cheapest().unwrap().price, Money::eur(100));
+        assert_eq!(
+            format_comparison("milk", &comparison),
+            "🏆 Cheapest milk by pack price: milk_walmart at walmart\n\
+             1. milk_walmart (walmart): 1,00 € · 1,25 US$\n\
+             2. milk_continente (continente): 1,05 €"
+        );
+    }
+
+    #[test]
+    fn test_empty_comparison() {
+        let comparison = compare(
+            vec![(product(1, "continente"), None)],
+            &ExchangeRates::default(),
+            Currency::EUR,
+        );
+        assert!(comparison.cheapest().is_none());
+        assert_eq!(
+            format_comparison("milk", &comparison),
+            "No prices to compare for 'milk' yet."
+        );
+    }
+}
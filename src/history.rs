@@ -0,0 +1,271 @@
+use crate::money::Money;
+use crate::price_history::PriceObservation;
+use crate::service_runs::format_stored_time;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+/// Change points listed per page of `/history`.
+pub const PAGE_SIZE: usize = 10;
+/// Prefix of the callback data of the `/history` paging buttons.
+pub const CALLBACK_PREFIX: &str = "history";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistorySummary {
+    pub current: Money,
+    pub min: Money,
+    pub max: Money,
+    pub avg: Money,
+    pub count: usize,
+}
+
+/// Price at a point where it differs from the previous observation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangePoint {
+    pub timestamp: String,
+    pub price: Money,
+    pub previous: Option<Money>,
+}
+
+/// Where a `/history` message is, encoded in the paging buttons' callback data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HistoryPage {
+    pub product_id: i64,
+    pub days: Option<i64>,
+    pub page: usize,
+}
+
+impl HistoryPage {
+    pub fn to_callback_data(self) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            CALLBACK_PREFIX,
+            self.product_id,
+            self.days.map_or("all".to_string(), |days| days.to_string()),
+            self.page
+        )
+    }
+
+    pub fn from_callback_data(data: &str) -> Option<Self> {
+        let mut parts = data.split(':');
+        if parts.next()? != CALLBACK_PREFIX {
+            return None;
+        }
+        let product_id = parts.next()?.parse().ok()?;
+        let days = match parts.next()? {
+            "all" => None,
+            days => Some(days.parse().ok()?),
+        };
+        let page = parts.next()?.parse().ok()?;
+        Some(Self {
+            product_id,
+            days,
+            page,
+        })
+    }
+}
+
+/// Parses the `/history` period, e.g. `30d`, `12w`, `6m` (months), `1y` or `all`.
+pub fn parse_period_days(text: &str) -> Option<Option<i64>> {
+    let text = text.trim().to_lowercase();
+    if text == "all" {
+        return Some(None);
+    }
+    let unit = text.chars().last()?;
+    let value: i64 = text[..text.len() - unit.len_utf8()].parse().ok()?;
+    let days = match unit {
+        'd' => value,
+        'w' => value * 7,
+        'm' => value * 30,
+        'y' => value * 365,
+        _ => return None,
+    };
+    (days > 0).then_some(Some(days))
+}
+
+/// Summary in the currency of the latest observation, whose prices are compared by cents.
+pub fn summarize(observations: &[PriceObservation]) -> Option<HistorySummary> {
+    let current = observations.last()?.price();
+    let cents: Vec<i64> = observations
+        .iter()
+        .map(|observation| observation.price_cents)
+        .collect();
+    let money = |cents: i64| Money::new(cents, current.currency);
+    let min = money(*cents.iter().min()?);
+    let max = money(*cents.iter().max()?);
+    let avg = money(cents.iter().sum::<i64>()).divide(cents.len() as f32);
+    Some(HistorySummary {
+        current,
+        min,
+        max,
+        avg,
+        count: observations.len(),
+    })
+}
+
+/// First observation plus every observation whose price differs from the one before it.
+pub fn change_points(observations: &[PriceObservation]) -> Vec<ChangePoint> {
+    let mut points: Vec<ChangePoint> = vec![];
+    for observation in observations {
+        let previous = points.last().map(|point| point.price);
+        if previous != Some(observation.price()) {
+            points.push(ChangePoint {
+                timestamp: observation.timestamp.clone(),
+                price: observation.price(),
+                previous,
+            });
+        }
+    }
+    points
+}
+
+pub fn page_count(items: usize) -> usize {
+    items.div_ceil(PAGE_SIZE).max(1)
+}
+
+/// Formats the summary and one page of change points, most recent first.
+pub fn format_history(
+    product_name: &str,
+    days: Option<i64>,
+    observations: &[PriceObservation],
+    page: usize,
+) -> String {
+    let period = days.map_or("all time".to_string(), |days| format!("last {} days", days));
+    let Some(summary) = summarize(observations) else {
+        return format!("No prices recorded for {} ({}).", product_name, period);
+    };
+
+    let points = change_points(observations);
+    let pages = page_count(points.len());
+    let page = page.min(pages - 1);
+    let mut lines = vec![
+        format!("📈 {}, {}", product_name, period),
+        format!("current: {}", summary.current),
+        format!(
+            "min: {} · max: {} · avg: {}",
+            summary.min, summary.max, summary.avg
+        ),
+        format!("observations: {}", summary.count),
+        String::new(),
+        format!("Changes (page {}/{}):", page + 1, pages),
+    ];
+    for point in points.iter().rev().skip(page * PAGE_SIZE).take(PAGE_SIZE) {
+        let delta = point
+            .previous
+            .map(|previous| format!(" ({:+})", point.price - previous))
+            .unwrap_or_default();
+        lines.push(format!(
+            "{}  {}{}",
+            format_stored_time(&point.timestamp),
+            point.price,
+            delta
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Previous/next buttons for a history with `pages` pages, like the `/list` keyboard.
+pub fn make_history_keyboard(current: HistoryPage, pages: usize) -> InlineKeyboardMarkup {
+    let mut row = vec![];
+    if current.page > 0 {
+        let previous = HistoryPage {
+            page: current.page - 1,
+            ..current
+        };
+        row.push(InlineKeyboardButton::callback(
+            "◀ Newer",
+            previous.to_callback_data(),
+        ));
+    }
+    if current.page + 1 < pages {
+        let next = HistoryPage {
+            page: current.page + 1,
+            ..current
+        };
+        row.push(InlineKeyboardButton::callback(
+            "Older ▶",
+            next.to_callback_data(),
+        ));
+    }
+    InlineKeyboardMarkup::new(vec![row])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observations() -> Vec<PriceObservation> {
+        vec![
+            PriceObservation::test("2024-06-01 10:00:00", 129),
+            PriceObservation::test("2024-06-01 14:00:00", 129),
+            PriceObservation::test("2024-06-02 10:00:00", 119),
+            PriceObservation::test("2024-06-03 10:00:00", 139),
+            PriceObservation::test("2024-06-04 10:00:00", 139),
+        ]
+    }
+
+    #[test]
+    fn test_summarize() {
+        let summary = summarize(&observations()).unwrap();
+        assert_eq!(summary.current, Money::eur(139));
+        assert_eq!(summary.min, Money::eur(119));
+        assert_eq!(summary.max, Money::eur(139));
+        assert_eq!(summary.avg, Money::eur(131));
+        assert_eq!(summary.count, 5);
+        assert_eq!(summarize(&[]), None);
+    }
+
+    #[test]
+    fn test_change_points() {
+        let points = change_points(&observations());
+        let prices: Vec<i64> = points.iter().map(|point| point.price.cents).collect();
+        assert_eq!(prices, vec![129, 119, 139]);
+        assert_eq!(points[0].previous, None);
+        assert_eq!(points[2].previous, Some(Money::eur(119)));
+    }
+
+    #[test]
+    fn test_parse_period_days() {
+        assert_eq!(parse_period_days("30d"), Some(Some(30)));
+        assert_eq!(parse_period_days("2w"), Some(Some(14)));
+        assert_eq!(parse_period_days("1y"), Some(Some(365)));
+        assert_eq!(parse_period_days("all"), Some(None));
+        assert_eq!(parse_period_days("0d"), None);
+        assert_eq!(parse_period_days("soon"), None);
+    }
+
+    #[test]
+    fn test_callback_data_round_trip() {
+        let page = HistoryPage {
+            product_id: 3,
+            days: Some(30),
+            page: 2,
+        };
+        assert_eq!(page.to_callback_data(), "history:3:30:2");
+        assert_eq!(
+            HistoryPage::from_callback_data("history:3:30:2"),
+            Some(page)
+        );
+        let page = HistoryPage { days: None, ..page };
+        assert_eq!(
+            HistoryPage::from_callback_data(&page.to_callback_data()),
+            Some(page)
+        );
+        assert_eq!(HistoryPage::from_callback_data("[1] mimosa_milk: on"), None);
+    }
+
+    #[test]
+    fn test_paging() {
+        let many: Vec<PriceObservation> = (0..25)
+            .map(|i| PriceObservation::test("2024-06-01 10:00:00", 100 + i))
+            .collect();
+        assert_eq!(page_count(change_points(&many).len()), 3);
+        let text = format_history("milk", Some(30), &many, 2);
+        assert!(text.contains("Changes (page 3/3):"));
+        assert!(text.lines().any(|line| line.ends_with("  1,00 €")));
+        assert!(text
+            .lines()
+            .any(|line| line.ends_with("  1,04 € (+0,01 €)")));
+        assert!(!text
+            .lines()
+            .any(|line| line.ends_with("  1,05 € (+0,01 €)")));
+    }
+}
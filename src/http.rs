@@ -0,0 +1,417 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Response, StatusCode, Url};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use tokio::sync::Mutex;
+use tokio::time::{sleep_until, Duration, Instant};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_HOST_INTERVAL: Duration = Duration::from_secs(2);
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(2);
+/// Longest wait before a retry, a `Retry-After` asking for more is shortened to it.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(2 * 60);
+
+/// How the shared client talks to retailers, read from the environment by [`Self::from_env`].
+#[derive(Clone, Debug)]
+pub struct HttpConfig {
+    pub user_agent: String,
+    pub timeout: Duration,
+    /// Retries after the first attempt on 5xx, 429, timeouts and connection errors.
+    pub max_retries: u32,
+    pub base_retry_delay: Duration,
+    pub max_retry_delay: Duration,
+    /// Minimum time between two requests to the same host.
+    pub host_interval: Duration,
+    /// Intervals replacing [`Self::host_interval`] for a domain and its subdomains.
+    pub host_intervals: HashMap<String, Duration>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: format!(
+                "telebot/{} (Telegram price tracker)",
+                env!("CARGO_PKG_VERSION")
+            ),
+            timeout: DEFAULT_TIMEOUT,
+            max_retries: DEFAULT_MAX_RETRIES,
+            base_retry_delay: BASE_RETRY_DELAY,
+            max_retry_delay: MAX_RETRY_DELAY,
+            host_interval: DEFAULT_HOST_INTERVAL,
+            host_intervals: HashMap::new(),
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Defaults overridden by `HTTP_USER_AGENT`, `HTTP_TIMEOUT_SECS`, `HTTP_MAX_RETRIES`,
+    /// `HTTP_HOST_INTERVAL_SECS` and `HTTP_HOST_INTERVALS`, unparsable values being ignored.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        let var = |name: &str| std::env::var(name).ok();
+        let seconds = |name: &str| {
+            var(name)
+                .and_then(|value| value.parse::<f64>().ok())
+                .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                .map(Duration::from_secs_f64)
+        };
+        if let Some(user_agent) = var("HTTP_USER_AGENT") {
+            config.user_agent = user_agent;
+        }
+        if let Some(timeout) = seconds("HTTP_TIMEOUT_SECS") {
+            config.timeout = timeout;
+        }
+        if let Some(max_retries) = var("HTTP_MAX_RETRIES").and_then(|value| value.parse().ok()) {
+            config.max_retries = max_retries;
+        }
+        if let Some(host_interval) = seconds("HTTP_HOST_INTERVAL_SECS") {
+            config.host_interval = host_interval;
+        }
+        if let Some(host_intervals) = var("HTTP_HOST_INTERVALS") {
+            config.host_intervals = parse_host_intervals(&host_intervals);
+        }
+        config
+    }
+
+    /// Minimum time between two requests to `host`, from the most specific domain of
+    /// [`Self::host_intervals`] it belongs to.
+    pub fn interval_for(&self, host: &str) -> Duration {
+        self.host_intervals
+            .iter()
+            .filter(|(domain, _)| {
+                host == domain.as_str() || host.ends_with(&format!(".{}", domain))
+            })
+            .max_by_key(|(domain, _)| domain.len())
+            .map_or(self.host_interval, |(_, interval)| *interval)
+    }
+}
+
+/// Reads per-host intervals such as `continente.pt=5, auchan.pt=0.5`, in seconds, skipping
+/// unparsable entries.
+fn parse_host_intervals(text: &str) -> HashMap<String, Duration> {
+    text.split(',')
+        .filter_map(|entry| {
+            let (domain, seconds) = entry.split_once('=')?;
+            let seconds = seconds.trim().parse::<f64>().ok()?;
+            let domain = domain.trim().trim_start_matches("www.").to_lowercase();
+            (!domain.is_empty() && seconds.is_finite() && seconds >= 0.0)
+                .then(|| (domain, Duration::from_secs_f64(seconds)))
+        })
+        .collect()
+}
+
+/// HTTP client shared by every service: one connection pool and cookie jar, a custom
+/// User-Agent, per-host rate limiting and retries of transient failures.
+pub struct HttpClient {
+    client: reqwest::Client,
+    config: HttpConfig,
+    /// Earliest time the next request to each host (and explicit port) may start.
+    next_request: Mutex<HashMap<String, Instant>>,
+}
+
+impl HttpClient {
+    pub fn new(config: HttpConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(&config.user_agent)
+            .timeout(config.timeout)
+            .cookie_store(true)
+            .build()
+            .expect("valid HTTP client configuration");
+        Self {
+            client,
+            config,
+            next_request: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sends a GET to `url`, retrying 5xx and 429 responses, timeouts and connection errors
+    /// with exponential backoff, or after the delay in `Retry-After` when the server sends one,
+    /// up to [`HttpConfig::max_retry_delay`]. The last response is returned once retries run
+    /// out, so callers still see its status.
+    pub async fn get(&self, url: &str) -> Result<Response, reqwest::Error> {
+        self.get_with_headers(url, HeaderMap::new()).await
+    }
+
+    /// Like [`Self::get`], sending `headers` on every attempt.
+    pub async fn get_with_headers(
+        &self,
+        url: &str,
+        headers: HeaderMap,
+    ) -> Result<Response, reqwest::Error> {
+        let mut attempt = 0;
+        loop {
+            self.wait_for_host(url).await;
+            attempt += 1;
+            let result = self.client.get(url).headers(headers.clone()).send().await;
+            let delay = match &result {
+                Ok(response) if is_retryable(response.status()) => {
+                    match retry_after(response.headers(), Utc::now()) {
+                        Some(delay) => delay.min(self.config.max_retry_delay),
+                        None => self.retry_delay(attempt, rand::random::<f64>()),
+                    }
+                }
+                Err(err) if err.is_timeout() || err.is_connect() => {
+                    self.retry_delay(attempt, rand::random::<f64>())
+                }
+                _ => return result,
+            };
+            if attempt > self.config.max_retries {
+                return result;
+            }
+
+            let reason = match &result {
+                Ok(response) => response.status().to_string(),
+                Err(err) => err.to_string(),
+            };
+            log::warn!(
+                "Request to {} failed ({}), retry {} in {:?}",
+                url,
+                reason,
+                attempt,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Waits for the turn of the host of `url`, reserving the next one so concurrent requests
+    /// to a host are spread by [`HttpConfig::interval_for`] the host.
+    async fn wait_for_host(&self, url: &str) {
+        let Some((key, interval)) = Url::parse(url).ok().and_then(|url| {
+            let host = url.host_str()?;
+            let key = match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            };
+            Some((key, self.config.interval_for(host)))
+        }) else {
+            return;
+        };
+        let start = {
+            let mut next_request = self.next_request.lock().await;
+            let now = Instant::now();
+            let start = next_request.get(&key).map_or(now, |next| (*next).max(now));
+            next_request.insert(key, start + interval);
+            start
+        };
+        sleep_until(start).await;
+    }
+
+    /// Doubles the delay on every attempt, spread by ±50% according to `jitter` in `[0, 1)`
+    /// like service restarts, and never longer than the maximum.
+    fn retry_delay(&self, attempt: u32, jitter: f64) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        self.config
+            .base_retry_delay
+            .saturating_mul(1 << exponent)
+            .mul_f64(0.5 + jitter)
+            .min(self.config.max_retry_delay)
+    }
+}
+
+/// The client used for every outgoing request, configured from the environment.
+pub fn client() -> &'static HttpClient {
+    static CLIENT: OnceLock<HttpClient> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        let config = HttpConfig::from_env();
+        // Tests serve pages from pooled local mock servers that would wait on each other.
+        #[cfg(test)]
+        let config = HttpConfig {
+            host_interval: Duration::ZERO,
+            host_intervals: HashMap::new(),
+            ..config
+        };
+        HttpClient::new(config)
+    })
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Delay asked by a `Retry-After` header, either in seconds or as an HTTP date.
+fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn test_client() -> HttpClient {
+        HttpClient::new(HttpConfig {
+            user_agent: "telebot-test".to_string(),
+            timeout: Duration::from_secs(5),
+            max_retries: 2,
+            base_retry_delay: Duration::from_millis(10),
+            max_retry_delay: Duration::from_millis(500),
+            host_interval: Duration::ZERO,
+            host_intervals: HashMap::new(),
+        })
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:27:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers, now), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(120)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(60)));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers, now), None);
+    }
+
+    #[test]
+    fn test_retry_delay_backoff() {
+        let client = test_client();
+        assert_eq!(client.retry_delay(1, 0.5), Duration::from_millis(10));
+        assert_eq!(client.retry_delay(3, 0.5), Duration::from_millis(40));
+        assert_eq!(client.retry_delay(30, 0.5), Duration::from_millis(500));
+        // Jitter never takes the delay past the maximum.
+        assert_eq!(client.retry_delay(30, 0.99), Duration::from_millis(500));
+        assert_eq!(client.retry_delay(7, 0.99), Duration::from_millis(500));
+        assert_eq!(client.retry_delay(5, 0.0), Duration::from_millis(80));
+    }
+
+    #[test]
+    fn test_host_intervals() {
+        let config = HttpConfig {
+            host_intervals: parse_host_intervals(
+                "www.continente.pt=5, loja.continente.pt = 0.5, auchan.pt=soon, =1",
+            ),
+            ..HttpConfig::default()
+        };
+        assert_eq!(config.host_intervals.len(), 2);
+        assert_eq!(
+            config.interval_for("www.continente.pt"),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            config.interval_for("loja.continente.pt"),
+            Duration::from_millis(500)
+        );
+        assert_eq!(config.interval_for("auchan.pt"), DEFAULT_HOST_INTERVAL);
+        assert_eq!(
+            config.interval_for("notcontinente.pt"),
+            DEFAULT_HOST_INTERVAL
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let unavailable = server
+            .mock("GET", "/page")
+            .with_status(503)
+            .expect(2)
+            .create();
+        let ok = server
+            .mock("GET", "/page")
+            .match_header("user-agent", "telebot-test")
+            .with_status(200)
+            .with_body("ok")
+            .create();
+
+        let response = test_client()
+            .get(&format!("{}/page", server.url()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        unavailable.assert();
+        ok.assert();
+    }
+
+    #[tokio::test]
+    async fn test_clamps_long_retry_after() {
+        let mut server = mockito::Server::new_async().await;
+        let limited = server
+            .mock("GET", "/page")
+            .with_status(429)
+            .with_header("retry-after", "3600")
+            .expect(1)
+            .create();
+        let ok = server.mock("GET", "/page").with_status(200).create();
+
+        let client = HttpClient::new(HttpConfig {
+            max_retry_delay: Duration::from_millis(100),
+            ..test_client().config
+        });
+        let started = Instant::now();
+        let response = client.get(&format!("{}/page", server.url())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        limited.assert();
+        ok.assert();
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_on_client_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/page")
+            .with_status(404)
+            .expect(1)
+            .create();
+
+        let response = test_client()
+            .get(&format!("{}/page", server.url()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_host_rate_limit_and_cookies() {
+        let mut server = mockito::Server::new_async().await;
+        let login = server
+            .mock("GET", "/login")
+            .with_status(200)
+            .with_header("set-cookie", "session=abc; Path=/")
+            .create();
+        let page = server
+            .mock("GET", "/page")
+            .match_header("cookie", "session=abc")
+            .with_status(200)
+            .create();
+
+        let client = HttpClient::new(HttpConfig {
+            host_interval: Duration::from_millis(200),
+            host_intervals: parse_host_intervals("example.com=30"),
+            ..test_client().config
+        });
+        let started = Instant::now();
+        client
+            .get(&format!("{}/login", server.url()))
+            .await
+            .unwrap();
+        let response = client.get(&format!("{}/page", server.url())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        login.assert();
+        page.assert();
+    }
+}
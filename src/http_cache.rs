@@ -0,0 +1,148 @@
+use crate::db;
+use reqwest::header::{
+    HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use sha2::{Digest, Sha256};
+use sqlx::FromRow;
+
+/// How a page fetch uses the `http_cache` table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Plain request that leaves the cache alone, for one-off checks that must read the page.
+    Bypass,
+    /// Plain request whose validators and hash are stored for the next revalidation, once the
+    /// page gave a price or said the product is unavailable.
+    Refresh,
+    /// Conditional request, the page is unchanged on a 304 or when the same body comes back.
+    Revalidate,
+}
+
+/// What is known about the last version of the page at `url`.
+#[derive(Clone, Debug, FromRow, PartialEq)]
+pub struct CacheEntry {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// SHA-256 of the body, for servers that send neither validator.
+    pub content_hash: String,
+}
+
+impl CacheEntry {
+    /// Entry for `body` received from `url` with the response `headers`.
+    pub fn new(url: &str, headers: &HeaderMap, body: &str) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            url: url.to_string(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            content_hash: content_hash(body),
+        }
+    }
+
+    /// `If-None-Match` and `If-Modified-Since` headers revalidating this entry.
+    pub fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let validators = [
+            (IF_NONE_MATCH, &self.etag),
+            (IF_MODIFIED_SINCE, &self.last_modified),
+        ];
+        for (name, value) in validators {
+            if let Some(value) = value.as_deref().and_then(|value| value.parse().ok()) {
+                headers.insert(name, value);
+            }
+        }
+        headers
+    }
+}
+
+pub fn content_hash(body: &str) -> String {
+    format!("{:x}", Sha256::digest(body.as_bytes()))
+}
+
+pub async fn get_entry(url: &str) -> Option<CacheEntry> {
+    let db = db::get_db().await;
+    let entry_query = sqlx::query_as::<_, CacheEntry>(
+        "SELECT url, etag, last_modified, content_hash FROM http_cache WHERE url = ?",
+    )
+    .bind(url)
+    .fetch_optional(&db)
+    .await;
+
+    match entry_query {
+        Ok(entry) => entry,
+        Err(err) => {
+            log::error!("Failed to query cached response of {}! {:?}", url, err);
+            None
+        }
+    }
+}
+
+pub async fn store_entry(entry: &CacheEntry) {
+    let db = db::get_db().await;
+    let upsert_result = sqlx::query(
+        "INSERT INTO http_cache (url, etag, last_modified, content_hash) VALUES (?, ?, ?, ?)
+         ON CONFLICT (url) DO UPDATE
+         SET etag = excluded.etag, last_modified = excluded.last_modified,
+             content_hash = excluded.content_hash, update_time = CURRENT_TIMESTAMP",
+    )
+    .bind(&entry.url)
+    .bind(&entry.etag)
+    .bind(&entry.last_modified)
+    .bind(&entry.content_hash)
+    .execute(&db)
+    .await;
+
+    if let Err(err) = upsert_result {
+        log::error!("Failed to cache response of {}! {:?}", entry.url, err);
+    }
+}
+
+/// Drops every entry, so the next checks read whole pages again.
+pub async fn clear() {
+    let db = db::get_db().await;
+    if let Err(err) = sqlx::query("DELETE FROM http_cache").execute(&db).await {
+        log::error!("Failed to clear the HTTP cache! {:?}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(
+            content_hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_ne!(content_hash("€1,29"), content_hash("€1,35"));
+    }
+
+    #[test]
+    fn test_conditional_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        let entry = CacheEntry::new("https://example.com/page", &headers, "body");
+        assert_eq!(entry.last_modified, None);
+
+        let conditional = entry.conditional_headers();
+        assert_eq!(conditional.get(IF_NONE_MATCH).unwrap(), "\"v1\"");
+        assert_eq!(conditional.get(IF_MODIFIED_SINCE), None);
+
+        headers.insert(
+            LAST_MODIFIED,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        let conditional =
+            CacheEntry::new("https://example.com/page", &headers, "body").conditional_headers();
+        assert_eq!(
+            conditional.get(IF_MODIFIED_SINCE).unwrap(),
+            "Wed, 21 Oct 2015 07:28:00 GMT"
+        );
+    }
+}
@@ -1,23 +1,52 @@
+pub mod alerts;
+pub mod chart;
 pub mod chat;
 pub mod constants;
 pub mod db;
+pub mod exchange_rates;
+pub mod groups;
+pub mod history;
+pub mod http;
+pub mod http_cache;
 mod milk_price;
+pub mod money;
+pub mod page_snapshots;
+pub mod price_history;
+pub mod products;
+pub mod promotions;
+pub mod retailers;
+pub mod scheduler;
+pub mod scraper_health;
+pub mod scrapers;
+pub mod service_runs;
 pub mod services;
+pub mod stock;
+pub mod structured_data;
+pub mod subscriptions;
+pub mod supervisor;
+pub mod units;
+pub mod users;
 
+use history::HistoryPage;
+use http_cache::CachePolicy;
+use money::Currency;
+use products::Product;
+use scheduler::Schedule;
 use services::Services;
 use std::sync::{Arc, OnceLock};
 use tokio::sync::RwLock as AsyncRwLock;
-use tokio::time::Duration;
+use users::Role;
 
 use regex::Regex;
+use reqwest::Url;
 
 use std::error::Error;
 use teloxide::{
     payloads::SendMessageSetters,
     prelude::*,
     types::{
-        InlineKeyboardButton, InlineKeyboardMarkup, InlineQueryResultArticle, InputMessageContent,
-        InputMessageContentText, Me,
+        InlineKeyboardButton, InlineKeyboardMarkup, InlineQueryResultArticle, InputFile,
+        InputMessageContent, InputMessageContentText, Me,
     },
     utils::command::BotCommands,
 };
@@ -26,10 +55,8 @@ use teloxide::dispatching::UpdateHandler;
 
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
-const MILK_URL: &str =
-    "https://www.continente.pt/produto/leite-proteina-sem-lactose-mimosa-7652960.html";
-// const FOUR_HOURS_IN_SECONDS: u64 = 60 * 60 * 4;
-const FOUR_HOURS_IN_SECONDS: u64 = 15;
+/// Default schedule of price checker services, change it per service with `/schedule`.
+const PRICE_CHECK_SCHEDULE: &str = "every 4h jitter 10m";
 
 #[derive(BotCommands, Clone)]
 #[command(
@@ -43,8 +70,89 @@ enum Command {
     Version,
     #[command(description = "List available services, use ls command.")]
     List,
-    #[command(description = "Query current mimosa milk price in Continente.")]
-    MilkPrice,
+    #[command(description = "Show health and last runs of services: /status [service].")]
+    Status(String),
+    #[command(description = "Show a service state or send it a command: /service <name> [args].")]
+    Service(String),
+    #[command(
+        description = "Show or change when a service runs: /schedule <service> [spec|default]."
+    )]
+    Schedule(String),
+    #[command(description = "List tracked products.")]
+    Products,
+    #[command(description = "Query current price of a tracked product, e.g. /price mimosa_milk.")]
+    Price(String),
+    #[command(
+        description = "Show price history of a product: /history <product> [30d|12w|6m|1y|all]."
+    )]
+    History(String),
+    #[command(
+        description = "Draw the price history of a product: /chart <product> [30d|12w|6m|1y|all]."
+    )]
+    Chart(String),
+    #[command(
+        description = "Compare the retailers of a product group: /compare [group], lists groups without one."
+    )]
+    Compare(String),
+    #[command(
+        description = "Group products sold by different retailers: /group <group> <product>..."
+    )]
+    Group(String),
+    #[command(
+        description = "Remove products from a group, or the whole group: /ungroup <group> [product]..."
+    )]
+    Ungroup(String),
+    #[command(description = "Start tracking a product: /track <url> [name].")]
+    Track(String),
+    #[command(description = "Stop tracking a product: /untrack <name>.")]
+    Untrack(String),
+    #[command(
+        description = "Alert on a price boundary: /alert <product> below|above <price> or drop <percent>%, e.g. below 0.90/l for the unit price."
+    )]
+    Alert(String),
+    #[command(description = "List price alerts: /alerts [product].")]
+    Alerts(String),
+    #[command(description = "Remove a price alert: /unalert <id>.")]
+    Unalert(String),
+    #[command(description = "Show or change the currency prices are shown in: /currency [code].")]
+    Currency(String),
+    #[command(
+        description = "Admin: set how many units of a currency one euro buys: /rate <code> <rate>."
+    )]
+    Rate(String),
+    #[command(description = "Get notifications from a service: /subscribe [service].")]
+    Subscribe(String),
+    #[command(description = "Stop notifications from a service: /unsubscribe <service>.")]
+    Unsubscribe(String),
+    #[command(description = "Admin: reload scraper definitions from the scrapers table.")]
+    Scrapers,
+    #[command(
+        description = "Admin: show what the scraper of a page extracts: /testscraper <url>."
+    )]
+    TestScraper(String),
+    #[command(description = "Admin: allow a user to use the bot: /grant <user_id> [admin|user].")]
+    Grant(String),
+    #[command(description = "Admin: block a user from using the bot: /revoke <user_id>.")]
+    Revoke(String),
+}
+
+impl Command {
+    /// Commands only admins can use, keep the Access Control section of the README in sync.
+    fn requires_admin(&self) -> bool {
+        match self {
+            Command::Service(args) | Command::Schedule(args) => {
+                args.split_whitespace().nth(1).is_some()
+            }
+            Command::Track(_)
+            | Command::Untrack(_)
+            | Command::Rate(_)
+            | Command::Scrapers
+            | Command::TestScraper(_)
+            | Command::Grant(_)
+            | Command::Revoke(_) => true,
+            _ => false,
+        }
+    }
 }
 
 fn get_services() -> &'static Arc<AsyncRwLock<Services>> {
@@ -60,30 +168,28 @@ async fn main() {
 
     db::init().await;
 
+    // `telebot replay [product]` checks the current extractors against stored pages offline.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("replay") {
+        scrapers::reload().await;
+        page_snapshots::replay(args.get(1).map(String::as_str)).await;
+        return;
+    }
+
+    users::bootstrap_admin(*constants::ADMIN_USER_ID).await;
+    scrapers::reload().await;
+
     log::info!("Starting purchase bot...");
 
     let bot = Bot::from_env();
 
-    {
-        let services = get_services();
-        {
-            let mut services_write = services.write().await;
-            services_write
-                .create_service(
-                    "mimosa_milk".to_string(),
-                    true,
-                    Box::new(|| {
-                        Box::pin(async {
-                            milk_price::price_periodically_checker_thread(
-                                MILK_URL,
-                                Duration::from_secs(FOUR_HOURS_IN_SECONDS),
-                            )
-                            .await
-                        })
-                    }),
-                )
-                .await;
-        }
+    get_services()
+        .write()
+        .await
+        .create_service(true, Arc::new(exchange_rates::ExchangeRatesService::new()))
+        .await;
+    for product in products::get_products().await {
+        create_price_checker_service(product).await;
     }
 
     Dispatcher::builder(bot, schema())
@@ -93,14 +199,96 @@ async fn main() {
         .await;
 }
 
+/// Registers a price checker service for `product`, one service per tracked product.
+async fn create_price_checker_service(product: Product) {
+    let schedule = Schedule::parse(PRICE_CHECK_SCHEDULE).expect("valid default schedule");
+    let service = milk_price::PriceCheckerService::new(product, schedule);
+    get_services()
+        .write()
+        .await
+        .create_service(true, Arc::new(service))
+        .await;
+}
+
 fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
     let command_handler = dptree::entry()
-        .branch(Update::filter_message().endpoint(message_handler))
-        .branch(Update::filter_callback_query().endpoint(callback_handler))
-        .branch(Update::filter_inline_query().endpoint(inline_query_handler));
+        .branch(
+            Update::filter_message()
+                .branch(dptree::filter_map_async(authorize_message).endpoint(message_handler))
+                .endpoint(unauthorized_message_handler),
+        )
+        .branch(
+            Update::filter_callback_query()
+                .branch(
+                    dptree::filter_map(history_page_callback)
+                        .filter_async(is_allowed_callback)
+                        .endpoint(history_callback_handler),
+                )
+                .branch(dptree::filter_async(is_admin_callback).endpoint(callback_handler))
+                .endpoint(unauthorized_callback_handler),
+        )
+        .branch(
+            Update::filter_inline_query()
+                .filter_async(is_allowed_inline_query)
+                .endpoint(inline_query_handler),
+        );
     command_handler
 }
 
+/// Role of an allowed user, `None` for unknown or blocked users so the update is rejected.
+async fn allowed_role(user_id: Option<UserId>) -> Option<Role> {
+    let role = users::get_role(user_id?).await?;
+    (role != Role::Blocked).then_some(role)
+}
+
+async fn authorize_message(msg: Message) -> Option<Role> {
+    allowed_role(msg.from().map(|user| user.id)).await
+}
+
+async fn is_allowed_callback(q: CallbackQuery) -> bool {
+    allowed_role(Some(q.from.id)).await.is_some()
+}
+
+fn history_page_callback(q: CallbackQuery) -> Option<HistoryPage> {
+    HistoryPage::from_callback_data(q.data.as_deref()?)
+}
+
+async fn is_admin_callback(q: CallbackQuery) -> bool {
+    allowed_role(Some(q.from.id)).await == Some(Role::Admin)
+}
+
+async fn is_allowed_inline_query(q: InlineQuery) -> bool {
+    allowed_role(Some(q.from.id)).await.is_some()
+}
+
+async fn unauthorized_message_handler(bot: Bot, msg: Message) -> HandlerResult {
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+    log::warn!("Rejected message from unauthorized user {}", user.id);
+    if users::get_role(user.id).await == Some(Role::Blocked) {
+        return Ok(());
+    }
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "You are not allowed to use this bot. Ask an admin to run /grant {}",
+            user.id
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn unauthorized_callback_handler(bot: Bot, q: CallbackQuery) -> HandlerResult {
+    log::warn!("Rejected callback from unauthorized user {}", q.from.id);
+    bot.answer_callback_query(q.id)
+        .text("Only admins can change services.")
+        .await?;
+    Ok(())
+}
+
 async fn help(bot: Bot, msg: Message) -> HandlerResult {
     bot.send_message(msg.chat.id, Command::descriptions().to_string())
         .await?;
@@ -116,12 +304,158 @@ async fn version(bot: Bot, msg: Message) -> HandlerResult {
 
 async fn list(bot: Bot, msg: Message) -> HandlerResult {
     let keyboard = make_keyboard().await;
-    bot.send_message(msg.chat.id, "Services:")
+    bot.send_message(msg.chat.id, services_overview().await)
         .reply_markup(keyboard)
         .await?;
     Ok(())
 }
 
+/// Describes every service along with the state it reports through `BotService::status`.
+async fn services_overview() -> String {
+    let mut lines = vec!["Services:".to_string()];
+    let services_guard = get_services().read().await;
+    for service_guard in services_guard.services.iter() {
+        let service = service_guard.lock().await;
+        let health = service.health();
+        lines.push(format!(
+            "\n[{}] {} ({})\n{}",
+            service.id,
+            service.name,
+            health.state,
+            service.service.description()
+        ));
+        if health.crashes > 0 {
+            lines.push(format!(
+                "  crashes: {}, last: {}",
+                health.crashes,
+                health.last_crash.unwrap_or_default()
+            ));
+        }
+        for (label, value) in service.service.status() {
+            lines.push(format!("  {}: {}", label, value));
+        }
+    }
+    lines.join("\n")
+}
+
+async fn status_command(bot: Bot, msg: Message, name: String) -> HandlerResult {
+    let name = name.trim();
+    let mut sections = vec![];
+    let services_guard = get_services().read().await;
+    for service_guard in services_guard.services.iter() {
+        let service = service_guard.lock().await;
+        if !name.is_empty() && service.name != name {
+            continue;
+        }
+        sections.push(service_status(&service).await);
+    }
+    drop(services_guard);
+
+    let text = if sections.is_empty() && !name.is_empty() {
+        format!("Service '{}' does not exist.", name)
+    } else if sections.is_empty() {
+        "There are no services.".to_string()
+    } else {
+        sections.join("\n\n")
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+/// Formats the health, run history and next scheduled run of one service.
+async fn service_status(service: &services::Service) -> String {
+    let health = service.health();
+    let stats = service_runs::get_stats(service.id).await;
+    let icon = match (health.state, stats.last_run_success) {
+        (supervisor::ServiceState::Failed, _) => "🔴",
+        (supervisor::ServiceState::Stopped, _) => "⚪",
+        (_, Some(false)) => "🟠",
+        _ => "🟢",
+    };
+
+    let mut lines = vec![format!("{} {} ({})", icon, service.name, health.state)];
+    lines.push(format!(
+        "  runs: {} ({} failed)",
+        stats.run_count, stats.failure_count
+    ));
+    if let Some(last_run) = &stats.last_run {
+        let outcome = if stats.last_run_success == Some(true) {
+            "ok"
+        } else {
+            "failed"
+        };
+        lines.push(format!(
+            "  last run: {} ({})",
+            service_runs::format_stored_time(last_run),
+            outcome
+        ));
+    }
+    if let Some(last_success) = &stats.last_success {
+        lines.push(format!(
+            "  last success: {}",
+            service_runs::format_stored_time(last_success)
+        ));
+    }
+    if let (Some(error), Some(time)) = (&stats.last_error, &stats.last_error_time) {
+        lines.push(format!(
+            "  last error: {} ({})",
+            error,
+            service_runs::format_stored_time(time)
+        ));
+    }
+    if health.crashes > 0 {
+        lines.push(format!(
+            "  crashes: {}, last: {}",
+            health.crashes,
+            health.last_crash.unwrap_or_default()
+        ));
+    }
+    if let Some(next_run) = service.next_run() {
+        lines.push(format!(
+            "  next run: {}",
+            service_runs::format_local_time(next_run)
+        ));
+    }
+    lines.join("\n")
+}
+
+async fn service_command(bot: Bot, msg: Message, args: String) -> HandlerResult {
+    let args = args.trim();
+    let (name, service_args) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    if name.is_empty() {
+        bot.send_message(msg.chat.id, "Usage: /service <name> [args]")
+            .await?;
+        return Ok(());
+    }
+
+    let service_guard = get_services().read().await.get_service(name).await;
+    let Some(service_guard) = service_guard else {
+        bot.send_message(msg.chat.id, format!("Service '{}' does not exist.", name))
+            .await?;
+        return Ok(());
+    };
+    let service = service_guard.lock().await.service.clone();
+
+    let text = if service_args.trim().is_empty() {
+        let mut lines = vec![format!("{}: {}", service.name(), service.description())];
+        for (label, value) in service.status() {
+            lines.push(format!("  {}: {}", label, value));
+        }
+        lines.join("\n")
+    } else {
+        match service.on_command(service_args.trim()).await {
+            Some(reply) => reply,
+            None => format!(
+                "Service '{}' doesn't understand '{}'.",
+                name,
+                service_args.trim()
+            ),
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
 async fn make_keyboard() -> InlineKeyboardMarkup {
     let mut keyboard: Vec<Vec<InlineKeyboardButton>> = vec![];
     let mut services_list = vec![];
@@ -148,17 +482,798 @@ async fn make_keyboard() -> InlineKeyboardMarkup {
 
     InlineKeyboardMarkup::new(keyboard)
 }
-async fn milk_price_command(bot: Bot, msg: Message) -> HandlerResult {
-    let milk_price = milk_price::get_price(MILK_URL).await.unwrap();
+async fn schedule_command(bot: Bot, msg: Message, args: String) -> HandlerResult {
+    let args = args.trim();
+    let (name, spec) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let spec = spec.trim();
+    if name.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "Usage: /schedule <service> [every <interval>|cron <expression>] \
+             [jitter <duration>] [between HH:MM-HH:MM] [tz <timezone>], or default",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let service_guard = get_services().read().await.get_service(name).await;
+    let Some(service_guard) = service_guard else {
+        bot.send_message(msg.chat.id, format!("Service '{}' does not exist.", name))
+            .await?;
+        return Ok(());
+    };
+    let mut service = service_guard.lock().await;
+
+    if spec.is_empty() {
+        let schedule = service.context().schedule().await;
+        bot.send_message(
+            msg.chat.id,
+            format!("Service '{}' runs on schedule: {}", name, schedule),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let update_result = if spec == "default" {
+        scheduler::clear_schedule(service.id).await
+    } else {
+        match Schedule::parse(spec) {
+            Ok(schedule) => scheduler::set_schedule(service.id, &schedule).await,
+            Err(err) => {
+                bot.send_message(msg.chat.id, format!("Invalid schedule: {}", err))
+                    .await?;
+                return Ok(());
+            }
+        }
+    };
+    if let Err(err) = update_result {
+        log::error!("Failed to update schedule of '{}': {:?}", name, err);
+        bot.send_message(
+            msg.chat.id,
+            format!("Failed to update schedule of '{}'.", name),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    // Restart the service so the current wait is replaced by one on the new schedule.
+    if service.enable {
+        service.end();
+        service.begin();
+    }
+    let schedule = service.context().schedule().await;
+    bot.send_message(
+        msg.chat.id,
+        format!("Service '{}' now runs on schedule: {}", name, schedule),
+    )
+    .await?;
+    Ok(())
+}
 
-    match milk_price {
-        Some(price) => {
-            bot.send_message(msg.chat.id, format!("Current milk price is: {} €", price))
+async fn products_command(bot: Bot, msg: Message) -> HandlerResult {
+    let products = products::get_products().await;
+    let text = if products.is_empty() {
+        "No products are being tracked.".to_string()
+    } else {
+        products
+            .iter()
+            .map(|product| format!("• {} ({}): {}", product.name, product.retailer, product.url))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn price_command(bot: Bot, msg: Message, name: String) -> HandlerResult {
+    let name = name.trim();
+    let Some(product) = products::get_product(name).await else {
+        bot.send_message(msg.chat.id, format!("Product '{}' is not tracked.", name))
+            .await?;
+        return Ok(());
+    };
+
+    let text = match milk_price::fetch_price(&product, CachePolicy::Bypass).await {
+        Ok(Some(snapshot)) => {
+            let currency = user_currency(&msg).await;
+            let converted = exchange_rates::get_rates()
+                .await
+                .convert(snapshot.price, currency)
+                .filter(|price| price.currency != snapshot.price.currency);
+            match converted {
+                Some(price) => format!(
+                    "Current {} price is: {} (≈ {})",
+                    product.name, snapshot, price
+                ),
+                None => format!("Current {} price is: {}", product.name, snapshot),
+            }
+        }
+        Ok(None) => format!("Could not find a price for {}.", product.name),
+        Err(milk_price::PriceError::Unavailable) => format!("{} is out of stock.", product.name),
+        Err(err) => format!("Failed to query {} price: {}", product.name, err),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn history_command(bot: Bot, msg: Message, args: String) -> HandlerResult {
+    let usage = "Usage: /history <product> [30d|12w|6m|1y|all]";
+    let mut args = args.split_whitespace();
+    let Some(name) = args.next() else {
+        bot.send_message(msg.chat.id, usage).await?;
+        return Ok(());
+    };
+    let Some(days) = args
+        .next()
+        .map_or(Some(Some(30)), history::parse_period_days)
+    else {
+        bot.send_message(msg.chat.id, usage).await?;
+        return Ok(());
+    };
+    let Some(product) = products::get_product(name).await else {
+        bot.send_message(msg.chat.id, format!("Product '{}' is not tracked.", name))
+            .await?;
+        return Ok(());
+    };
+
+    let page = HistoryPage {
+        product_id: product.id,
+        days,
+        page: 0,
+    };
+    let (text, keyboard) = render_history_page(&product, page).await;
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+async fn render_history_page(
+    product: &Product,
+    page: HistoryPage,
+) -> (String, InlineKeyboardMarkup) {
+    let observations =
+        price_history::get_observations(product.id, page.days.map(|days| days * 24 * 60 * 60))
+            .await;
+    let pages = history::page_count(history::change_points(&observations).len());
+    let text = history::format_history(&product.name, page.days, &observations, page.page);
+    (text, history::make_history_keyboard(page, pages))
+}
+
+async fn history_callback_handler(bot: Bot, q: CallbackQuery, page: HistoryPage) -> HandlerResult {
+    let product = products::get_product_by_id(page.product_id).await;
+    let Some(product) = product else {
+        bot.answer_callback_query(q.id)
+            .text("This product is no longer tracked.")
+            .await?;
+        return Ok(());
+    };
+
+    let (text, keyboard) = render_history_page(&product, page).await;
+    bot.answer_callback_query(q.id).await?;
+    if let Some(Message { id, chat, .. }) = q.message {
+        bot.edit_message_text(chat.id, id, text)
+            .reply_markup(keyboard)
+            .await?;
+    } else if let Some(id) = q.inline_message_id {
+        bot.edit_message_text_inline(id, text)
+            .reply_markup(keyboard)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn chart_command(bot: Bot, msg: Message, args: String) -> HandlerResult {
+    let usage = "Usage: /chart <product> [30d|12w|6m|1y|all]";
+    let mut args = args.split_whitespace();
+    let Some(name) = args.next() else {
+        bot.send_message(msg.chat.id, usage).await?;
+        return Ok(());
+    };
+    let Some(days) = args
+        .next()
+        .map_or(Some(Some(30)), history::parse_period_days)
+    else {
+        bot.send_message(msg.chat.id, usage).await?;
+        return Ok(());
+    };
+    let Some(product) = products::get_product(name).await else {
+        bot.send_message(msg.chat.id, format!("Product '{}' is not tracked.", name))
+            .await?;
+        return Ok(());
+    };
+
+    let observations =
+        price_history::get_observations(product.id, days.map(|days| days * 24 * 60 * 60)).await;
+    let period = days.map_or("all time".to_string(), |days| format!("last {} days", days));
+    let Some(summary) = history::summarize(&observations) else {
+        bot.send_message(
+            msg.chat.id,
+            format!("No prices recorded for {} ({}).", product.name, period),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let png_bytes = match chart::render_price_chart(&observations) {
+        Ok(png_bytes) => png_bytes,
+        Err(err) => {
+            log::error!("Failed to render chart of '{}': {}", product.name, err);
+            bot.send_message(msg.chat.id, format!("Failed to draw chart: {}", err))
                 .await?;
-            Ok(())
+            return Ok(());
         }
-        None => Ok(()),
+    };
+    let first = &observations[0].timestamp;
+    let last = &observations[observations.len() - 1].timestamp;
+    let caption = format!(
+        "📈 {}, {}\n{} → {}\ncurrent: {} · min: {} · max: {}",
+        product.name,
+        period,
+        service_runs::format_stored_time(first),
+        service_runs::format_stored_time(last),
+        summary.current,
+        summary.min,
+        summary.max
+    );
+    bot.send_photo(
+        msg.chat.id,
+        InputFile::memory(png_bytes).file_name(format!("{}.png", product.name)),
+    )
+    .caption(caption)
+    .await?;
+    Ok(())
+}
+
+async fn compare_command(bot: Bot, msg: Message, name: String) -> HandlerResult {
+    let name = name.trim();
+    if name.is_empty() {
+        let product_groups = groups::get_groups().await;
+        let text = if product_groups.is_empty() {
+            "No product groups yet, create one with /group <group> <product>...".to_string()
+        } else {
+            let mut lines = vec!["Product groups:".to_string()];
+            for group in product_groups {
+                let members = groups::get_members(group.id).await;
+                let names: Vec<&str> = members.iter().map(|member| member.name.as_str()).collect();
+                lines.push(format!("• {}: {}", group.name, names.join(", ")));
+            }
+            lines.join("\n")
+        };
+        bot.send_message(msg.chat.id, text).await?;
+        return Ok(());
     }
+
+    let Some(group) = groups::get_group(name).await else {
+        bot.send_message(msg.chat.id, format!("Group '{}' does not exist.", name))
+            .await?;
+        return Ok(());
+    };
+    let members = groups::get_members(group.id).await;
+    // Check every member now so the comparison is about current prices, the results are
+    // recorded and read back below along with the last known availability.
+    let checks = members
+        .iter()
+        .map(|member| milk_price::fetch_price(member, CachePolicy::Bypass));
+    for (member, result) in members.iter().zip(futures::future::join_all(checks).await) {
+        if let Err(err) = result {
+            log::warn!("Comparing '{}' with a stored price: {}", member.name, err);
+        }
+    }
+
+    let comparison = groups::compare(
+        groups::latest_snapshots(members).await,
+        &exchange_rates::get_rates().await,
+        user_currency(&msg).await,
+    );
+    bot.send_message(
+        msg.chat.id,
+        groups::format_comparison(&group.name, &comparison),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn group_command(bot: Bot, msg: Message, args: String) -> HandlerResult {
+    let mut args = args.split_whitespace();
+    let (Some(name), Some(first)) = (args.next(), args.next()) else {
+        bot.send_message(msg.chat.id, "Usage: /group <group> <product>...")
+            .await?;
+        return Ok(());
+    };
+
+    let mut added = vec![];
+    let mut lines = vec![];
+    for product_name in std::iter::once(first).chain(args) {
+        let Some(product) = products::get_product(product_name).await else {
+            lines.push(format!("Product '{}' is not tracked.", product_name));
+            continue;
+        };
+        match groups::add_member(name, product.id).await {
+            Ok(()) => added.push(product.name),
+            Err(err) => {
+                log::error!(
+                    "Failed to add '{}' to group '{}': {:?}",
+                    product.name,
+                    name,
+                    err
+                );
+                lines.push(format!("Failed to add '{}'.", product.name));
+            }
+        }
+    }
+    if !added.is_empty() {
+        lines.insert(0, format!("Group '{}' now has: {}", name, added.join(", ")));
+    }
+    bot.send_message(msg.chat.id, lines.join("\n")).await?;
+    Ok(())
+}
+
+async fn ungroup_command(bot: Bot, msg: Message, args: String) -> HandlerResult {
+    let mut args = args.split_whitespace();
+    let Some(name) = args.next() else {
+        bot.send_message(msg.chat.id, "Usage: /ungroup <group> [product]...")
+            .await?;
+        return Ok(());
+    };
+    let product_names: Vec<&str> = args.collect();
+
+    if product_names.is_empty() {
+        let text = match groups::remove_group(name).await {
+            Ok(true) => format!("Removed group '{}'.", name),
+            Ok(false) => format!("Group '{}' does not exist.", name),
+            Err(err) => {
+                log::error!("Failed to remove group '{}': {:?}", name, err);
+                format!("Failed to remove group '{}'.", name)
+            }
+        };
+        bot.send_message(msg.chat.id, text).await?;
+        return Ok(());
+    }
+
+    let mut lines = vec![];
+    for product_name in product_names {
+        let removed = match products::get_product(product_name).await {
+            Some(product) => groups::remove_member(name, product.id).await,
+            None => Ok(false),
+        };
+        lines.push(match removed {
+            Ok(true) => format!("Removed '{}' from '{}'.", product_name, name),
+            Ok(false) => format!("'{}' is not in group '{}'.", product_name, name),
+            Err(err) => {
+                log::error!(
+                    "Failed to remove '{}' from group '{}': {:?}",
+                    product_name,
+                    name,
+                    err
+                );
+                format!("Failed to remove '{}' from '{}'.", product_name, name)
+            }
+        });
+    }
+    bot.send_message(msg.chat.id, lines.join("\n")).await?;
+    Ok(())
+}
+
+async fn track_command(bot: Bot, msg: Message, args: String) -> HandlerResult {
+    let mut args = args.split_whitespace();
+    let Some(url) = args.next().and_then(|url| Url::parse(url).ok()) else {
+        bot.send_message(msg.chat.id, "Usage: /track <url> [name]")
+            .await?;
+        return Ok(());
+    };
+    if !matches!(url.scheme(), "http" | "https") {
+        bot.send_message(msg.chat.id, "Only http(s) URLs can be tracked.")
+            .await?;
+        return Ok(());
+    }
+
+    let Some(name) = args
+        .next()
+        .map(str::to_string)
+        .or_else(|| products::product_name_from_url(&url))
+    else {
+        bot.send_message(
+            msg.chat.id,
+            "Could not derive a name, use /track <url> <name>",
+        )
+        .await?;
+        return Ok(());
+    };
+    if products::get_product(&name).await.is_some() {
+        bot.send_message(
+            msg.chat.id,
+            format!("Product '{}' is already tracked.", name),
+        )
+        .await?;
+        return Ok(());
+    }
+    let retailer = products::retailer_from_url(&url).unwrap_or_else(|| "unknown".to_string());
+
+    let snapshot = match milk_price::get_snapshot(url.as_str()).await {
+        Ok(Some(snapshot)) => snapshot,
+        Ok(None) => {
+            bot.send_message(msg.chat.id, format!("Could not find a price at {}", url))
+                .await?;
+            return Ok(());
+        }
+        Err(err) => {
+            bot.send_message(msg.chat.id, format!("Failed to query {}: {}", url, err))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let product = match products::add_product(
+        &name,
+        url.as_str(),
+        &retailer,
+        snapshot.price.currency,
+    )
+    .await
+    {
+        Ok(product) => product,
+        Err(err) => {
+            log::error!("Failed to store product '{}': {:?}", name, err);
+            bot.send_message(msg.chat.id, format!("Failed to store product '{}'.", name))
+                .await?;
+            return Ok(());
+        }
+    };
+    price_history::record_observation(product.id, &snapshot, &product.retailer).await;
+    create_price_checker_service(product).await;
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "Now tracking '{}' ({}), current price is {}",
+            name, retailer, snapshot
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn untrack_command(bot: Bot, msg: Message, name: String) -> HandlerResult {
+    let name = name.trim();
+    if name.is_empty() {
+        bot.send_message(msg.chat.id, "Usage: /untrack <name>")
+            .await?;
+        return Ok(());
+    }
+
+    let text = match products::remove_product(name).await {
+        Ok(true) => {
+            get_services().write().await.remove_service(name).await;
+            format!("Stopped tracking '{}'.", name)
+        }
+        Ok(false) => format!("Product '{}' is not tracked.", name),
+        Err(err) => {
+            log::error!("Failed to remove product '{}': {:?}", name, err);
+            format!("Failed to remove product '{}'.", name)
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn alert_command(bot: Bot, msg: Message, args: String) -> HandlerResult {
+    let usage = "Usage: /alert <product> below|above <price> or /alert <product> drop <percent>%, \
+                 add /l, /kg or /un to the value to watch the unit price";
+    let Some((name, rule)) = args.trim().split_once(char::is_whitespace) else {
+        bot.send_message(msg.chat.id, usage).await?;
+        return Ok(());
+    };
+    let Some((rule, basis)) = alerts::parse_alert(rule, user_currency(&msg).await) else {
+        bot.send_message(msg.chat.id, usage).await?;
+        return Ok(());
+    };
+    let Some(product) = products::get_product(name).await else {
+        bot.send_message(msg.chat.id, format!("Product '{}' is not tracked.", name))
+            .await?;
+        return Ok(());
+    };
+
+    let text = match alerts::add_alert(product.id, rule, basis).await {
+        Ok(id) => {
+            let alert = alerts::Alert {
+                id,
+                product_id: product.id,
+                rule,
+                basis,
+            };
+            format!("Alert #{} set for '{}': {}.", id, product.name, alert)
+        }
+        Err(err) => {
+            log::error!("Failed to store alert for '{}': {:?}", product.name, err);
+            format!("Failed to store alert for '{}'.", product.name)
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn alerts_command(bot: Bot, msg: Message, name: String) -> HandlerResult {
+    let name = name.trim();
+    let tracked_products = if name.is_empty() {
+        products::get_products().await
+    } else {
+        products::get_product(name).await.into_iter().collect()
+    };
+
+    let mut lines = vec![];
+    for product in tracked_products {
+        for alert in alerts::get_alerts(product.id).await {
+            lines.push(format!("#{} {}: {}", alert.id, product.name, alert));
+        }
+    }
+    let text = if lines.is_empty() {
+        "No price alerts are set, every price change is notified.".to_string()
+    } else {
+        lines.join("\n")
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn unalert_command(bot: Bot, msg: Message, id: String) -> HandlerResult {
+    let Ok(id) = id.trim().trim_start_matches('#').parse::<i64>() else {
+        bot.send_message(msg.chat.id, "Usage: /unalert <id>")
+            .await?;
+        return Ok(());
+    };
+
+    let text = match alerts::remove_alert(id).await {
+        Ok(true) => format!("Alert #{} removed.", id),
+        Ok(false) => format!("Alert #{} does not exist.", id),
+        Err(err) => {
+            log::error!("Failed to remove alert #{}: {:?}", id, err);
+            format!("Failed to remove alert #{}.", id)
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+/// Preferred currency of the sender of `msg`, see [`users::get_currency`].
+async fn user_currency(msg: &Message) -> Currency {
+    match msg.from() {
+        Some(user) => users::get_currency(user.id).await,
+        None => Currency::EUR,
+    }
+}
+
+async fn currency_command(bot: Bot, msg: Message, code: String) -> HandlerResult {
+    let rates = exchange_rates::get_rates().await;
+    let code = code.trim();
+    if code.is_empty() {
+        let known = rates
+            .rates()
+            .iter()
+            .map(|(currency, rate)| format!("{} {}", currency, rate))
+            .collect::<Vec<_>>();
+        let mut text = format!("Prices are shown in {}.", user_currency(&msg).await);
+        if !known.is_empty() {
+            text.push_str(&format!("\nRates for 1 EUR: {}", known.join(", ")));
+        }
+        bot.send_message(msg.chat.id, text).await?;
+        return Ok(());
+    }
+
+    let Some(currency) = Currency::parse(code).filter(|currency| rates.rate(*currency).is_some())
+    else {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "Unknown currency '{}', see /currency for the known ones.",
+                code
+            ),
+        )
+        .await?;
+        return Ok(());
+    };
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+
+    let text = match users::set_currency(user.id, currency).await {
+        Ok(true) => format!("Prices are now shown in {}.", currency),
+        Ok(false) => "Only granted users can pick a currency.".to_string(),
+        Err(err) => {
+            log::error!("Failed to store currency of {}: {:?}", user.id, err);
+            "Failed to store your currency.".to_string()
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn rate_command(bot: Bot, msg: Message, args: String) -> HandlerResult {
+    let usage = "Usage: /rate <code> <rate>, e.g. /rate USD 1.08 for 1 EUR = 1.08 USD";
+    let mut args = args.split_whitespace();
+    let currency = args
+        .next()
+        .and_then(Currency::parse)
+        .filter(|currency| *currency != Currency::EUR);
+    let rate = args
+        .next()
+        .and_then(|rate| rate.replace(',', ".").parse::<f64>().ok())
+        .filter(|rate| rate.is_finite() && *rate > 0.0);
+    let (Some(currency), Some(rate), None) = (currency, rate, args.next()) else {
+        bot.send_message(msg.chat.id, usage).await?;
+        return Ok(());
+    };
+
+    let text = match exchange_rates::set_rate(currency, rate).await {
+        Ok(()) => format!("1 EUR is now {} {}.", rate, currency),
+        Err(err) => {
+            log::error!("Failed to store rate of {}: {:?}", currency, err);
+            format!("Failed to store rate of {}.", currency)
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn subscribe_command(bot: Bot, msg: Message, name: String) -> HandlerResult {
+    let name = name.trim();
+    if name.is_empty() {
+        let subscribed = subscriptions::get_subscriptions(msg.chat.id).await;
+        let text = if subscribed.is_empty() {
+            "This chat has no subscriptions, use /subscribe <service>.".to_string()
+        } else {
+            format!("This chat is subscribed to: {}", subscribed.join(", "))
+        };
+        bot.send_message(msg.chat.id, text).await?;
+        return Ok(());
+    }
+
+    let text = match subscriptions::subscribe(msg.chat.id, name).await {
+        Ok(true) => format!("Subscribed to '{}'.", name),
+        Ok(false) => format!("Service '{}' does not exist.", name),
+        Err(err) => {
+            log::error!(
+                "Failed to subscribe {} to '{}': {:?}",
+                msg.chat.id,
+                name,
+                err
+            );
+            format!("Failed to subscribe to '{}'.", name)
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn unsubscribe_command(bot: Bot, msg: Message, name: String) -> HandlerResult {
+    let name = name.trim();
+    if name.is_empty() {
+        bot.send_message(msg.chat.id, "Usage: /unsubscribe <service>")
+            .await?;
+        return Ok(());
+    }
+
+    let text = match subscriptions::unsubscribe(msg.chat.id, name).await {
+        Ok(true) => format!("Unsubscribed from '{}'.", name),
+        Ok(false) => format!("This chat is not subscribed to '{}'.", name),
+        Err(err) => {
+            log::error!(
+                "Failed to unsubscribe {} from '{}': {:?}",
+                msg.chat.id,
+                name,
+                err
+            );
+            format!("Failed to unsubscribe from '{}'.", name)
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn scrapers_command(bot: Bot, msg: Message) -> HandlerResult {
+    let problems = scrapers::reload().await;
+    // Pages cached under the old definitions must be read again by the new ones.
+    http_cache::clear().await;
+    let loaded = scrapers::loaded();
+    let mut lines = vec![if loaded.is_empty() {
+        "No scrapers defined, add rows to the scrapers table.".to_string()
+    } else {
+        format!("Loaded scrapers: {}", loaded.join(", "))
+    }];
+    lines.extend(
+        problems
+            .iter()
+            .map(|problem| format!("⚠️ skipped {}", problem)),
+    );
+    bot.send_message(msg.chat.id, lines.join("\n")).await?;
+    Ok(())
+}
+
+async fn test_scraper_command(bot: Bot, msg: Message, url: String) -> HandlerResult {
+    let Some(url) = Url::parse(url.trim()).ok() else {
+        bot.send_message(msg.chat.id, "Usage: /testscraper <url>")
+            .await?;
+        return Ok(());
+    };
+
+    let body = match milk_price::fetch_page(url.as_str()).await {
+        Ok(body) => body,
+        Err(err) => {
+            bot.send_message(msg.chat.id, format!("Failed to query {}: {}", url, err))
+                .await?;
+            return Ok(());
+        }
+    };
+    // Parsed documents aren't Send, so everything is read before the next await.
+    let text = {
+        let document = scraper::Html::parse_document(&body);
+        let scraper = scrapers::find_scraper(url.as_str());
+        let source = milk_price::price_source(url.as_str(), scraper.as_deref());
+        let mut lines =
+            scrapers::describe_extraction(url.as_str(), &document, scraper.as_deref(), source);
+        lines.push(match milk_price::extract_snapshot(&document, source) {
+            Ok(Some(snapshot)) => format!("Result: {}", snapshot),
+            Ok(None) => "Result: no price found".to_string(),
+            Err(err) => format!("Result: {}", err),
+        });
+        lines.join("\n")
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+fn parse_user_id(text: &str) -> Option<UserId> {
+    text.trim().parse::<u64>().ok().map(UserId)
+}
+
+async fn grant_command(bot: Bot, msg: Message, args: String) -> HandlerResult {
+    let usage = "Usage: /grant <user_id> [admin|user]";
+    let mut args = args.split_whitespace();
+    let Some(user_id) = args.next().and_then(parse_user_id) else {
+        bot.send_message(msg.chat.id, usage).await?;
+        return Ok(());
+    };
+    let role = match args.next().map(Role::parse) {
+        None => Role::User,
+        Some(Some(role)) if role != Role::Blocked => role,
+        Some(_) => {
+            bot.send_message(msg.chat.id, usage).await?;
+            return Ok(());
+        }
+    };
+
+    let text = match users::set_role(user_id, role).await {
+        Ok(()) => format!("User {} is now {}.", user_id, role),
+        Err(err) => {
+            log::error!("Failed to grant {} to user {}: {:?}", role, user_id, err);
+            format!("Failed to grant {} to user {}.", role, user_id)
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn revoke_command(bot: Bot, msg: Message, args: String) -> HandlerResult {
+    let Some(user_id) = parse_user_id(&args) else {
+        bot.send_message(msg.chat.id, "Usage: /revoke <user_id>")
+            .await?;
+        return Ok(());
+    };
+    if msg.from().map(|user| user.id) == Some(user_id) {
+        bot.send_message(msg.chat.id, "You can't revoke your own access.")
+            .await?;
+        return Ok(());
+    }
+
+    let text = match users::set_role(user_id, Role::Blocked).await {
+        Ok(()) => format!("User {} is now blocked.", user_id),
+        Err(err) => {
+            log::error!("Failed to revoke user {}: {:?}", user_id, err);
+            format!("Failed to revoke user {}.", user_id)
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
 }
 
 async fn inline_query_handler(
@@ -190,7 +1305,7 @@ async fn callback_handler(bot: Bot, q: CallbackQuery) -> Result<(), Box<dyn Erro
             log::info!("You chose: {}", service_string);
             text = "List of services exited.".to_string();
         } else {
-            let re = Regex::new(r"\[([0-9+])\] (.+): (.+)").unwrap();
+            let re = Regex::new(r"\[([0-9]+)\] (.+): (.+)").unwrap();
 
             if let Some(captures) = re.captures(&service_string) {
                 let id = captures.get(1).map_or("", |m| m.as_str());
@@ -235,13 +1350,43 @@ async fn message_handler(
     bot: Bot,
     msg: Message,
     me: Me,
+    role: Role,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
     if let Some(text) = msg.text() {
-        match BotCommands::parse(text, me.username()) {
+        let command = Command::parse(text, me.username());
+        if matches!(&command, Ok(command) if command.requires_admin()) && role != Role::Admin {
+            bot.send_message(msg.chat.id, "This command is only available to admins.")
+                .await?;
+            return Ok(());
+        }
+
+        match command {
             Ok(Command::Help) => help(bot, msg).await?,
             Ok(Command::Version) => version(bot, msg).await?,
             Ok(Command::List) => list(bot, msg).await?,
-            Ok(Command::MilkPrice) => milk_price_command(bot, msg).await?,
+            Ok(Command::Status(name)) => status_command(bot, msg, name).await?,
+            Ok(Command::Service(args)) => service_command(bot, msg, args).await?,
+            Ok(Command::Schedule(args)) => schedule_command(bot, msg, args).await?,
+            Ok(Command::Products) => products_command(bot, msg).await?,
+            Ok(Command::Price(name)) => price_command(bot, msg, name).await?,
+            Ok(Command::History(args)) => history_command(bot, msg, args).await?,
+            Ok(Command::Chart(args)) => chart_command(bot, msg, args).await?,
+            Ok(Command::Compare(name)) => compare_command(bot, msg, name).await?,
+            Ok(Command::Group(args)) => group_command(bot, msg, args).await?,
+            Ok(Command::Ungroup(args)) => ungroup_command(bot, msg, args).await?,
+            Ok(Command::Track(args)) => track_command(bot, msg, args).await?,
+            Ok(Command::Untrack(name)) => untrack_command(bot, msg, name).await?,
+            Ok(Command::Alert(args)) => alert_command(bot, msg, args).await?,
+            Ok(Command::Alerts(name)) => alerts_command(bot, msg, name).await?,
+            Ok(Command::Unalert(id)) => unalert_command(bot, msg, id).await?,
+            Ok(Command::Currency(code)) => currency_command(bot, msg, code).await?,
+            Ok(Command::Rate(args)) => rate_command(bot, msg, args).await?,
+            Ok(Command::Subscribe(name)) => subscribe_command(bot, msg, name).await?,
+            Ok(Command::Unsubscribe(name)) => unsubscribe_command(bot, msg, name).await?,
+            Ok(Command::Scrapers) => scrapers_command(bot, msg).await?,
+            Ok(Command::TestScraper(url)) => test_scraper_command(bot, msg, url).await?,
+            Ok(Command::Grant(args)) => grant_command(bot, msg, args).await?,
+            Ok(Command::Revoke(args)) => revoke_command(bot, msg, args).await?,
             Err(_) => {
                 bot.send_message(msg.chat.id, "Command not found!").await?;
             }
@@ -1,8 +1,29 @@
-use scraper::{Html, Selector};
+use scraper::Html;
 use thiserror::Error;
 
+use crate::alerts;
 use crate::chat;
-use tokio::time::{sleep, Duration};
+use crate::exchange_rates;
+use crate::groups;
+use crate::http;
+use crate::http_cache::{self, CacheEntry, CachePolicy};
+use crate::money::Money;
+use crate::page_snapshots;
+use crate::price_history;
+use crate::products::Product;
+use crate::promotions::{self, Promotion};
+use crate::retailers::{self, PriceSource};
+use crate::scheduler::Schedule;
+use crate::scraper_health::{self, CheckOutcome};
+use crate::scrapers;
+use crate::services::{BotService, ServiceContext};
+use crate::stock::{self, Availability};
+use crate::structured_data;
+use crate::units::{PackSize, UnitPrice};
+use chrono::Utc;
+use futures::future::BoxFuture;
+use std::fmt;
+use std::sync::{Arc, Mutex};
 
 #[derive(Error, Debug)]
 pub enum PriceError {
@@ -11,87 +32,546 @@ pub enum PriceError {
     #[error("Failed to parse HTML")]
     HtmlParseError,
     #[error("Failed to parse price: {0}")]
-    PriceParseError(#[from] std::num::ParseFloatError),
+    PriceParseError(String),
     #[error(transparent)]
     ReqwestError(#[from] reqwest::Error),
+    #[error("Product is out of stock")]
+    Unavailable,
+    #[error("Page has not changed since the last check")]
+    Unchanged,
 }
-pub async fn get_price(url: &str) -> Result<Option<f32>, PriceError> {
-    let response = reqwest::get(url).await?;
+
+/// Everything read from a product page in one price check.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriceSnapshot {
+    /// Price currently paid, the promotional price while a promotion runs.
+    pub price: Money,
+    pub unit_price: Option<UnitPrice>,
+    pub pack_size: Option<PackSize>,
+    pub promotion: Option<Promotion>,
+    pub availability: Availability,
+}
+
+impl PriceSnapshot {
+    pub fn new(price: Money) -> Self {
+        Self {
+            price,
+            unit_price: None,
+            pack_size: None,
+            promotion: None,
+            availability: Availability::InStock,
+        }
+    }
+}
+
+impl fmt::Display for PriceSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.price)?;
+        if let Some(pack_size) = &self.pack_size {
+            write!(f, " for {}", pack_size)?;
+        }
+        if let Some(unit_price) = &self.unit_price {
+            write!(f, " ({})", unit_price)?;
+        }
+        if let Some(promotion) = &self.promotion {
+            write!(f, " [{}]", promotion)?;
+        }
+        if self.availability == Availability::OutOfStock {
+            write!(f, ", {}", self.availability)?;
+        }
+        Ok(())
+    }
+}
+
+/// Queries the price at `url` along with its unit price and pack size, see
+/// [`extract_snapshot`] for how they are found.
+pub async fn get_snapshot(url: &str) -> Result<Option<PriceSnapshot>, PriceError> {
+    let scraper = scrapers::find_scraper(url);
+    get_snapshot_from(url, price_source(url, scraper.as_deref())).await
+}
+
+/// Source of the prices at `url`: its declarative scraper when one matches, else the built-in
+/// adapter of its host.
+pub fn price_source<'a>(
+    url: &str,
+    scraper: Option<&'a scrapers::ScraperDefinition>,
+) -> Option<&'a dyn PriceSource> {
+    match scraper {
+        Some(scraper) => Some(scraper),
+        None => retailers::source_for_url(url),
+    }
+}
+
+/// Queries the price at `url` with `source` as the CSS selector fallback.
+pub async fn get_snapshot_from(
+    url: &str,
+    source: Option<&dyn PriceSource>,
+) -> Result<Option<PriceSnapshot>, PriceError> {
+    let body = fetch_page(url).await?;
+    let document = Html::parse_document(&body);
+    extract_snapshot(&document, source)
+}
+
+/// Extracts the price with [`extract_price`], then the unit price, pack size, promotion and
+/// availability from `source`, or from the first built-in adapter that finds them when the
+/// host is unknown. The unit price is computed from the pack size when the page doesn't show
+/// it. Pages without a price that say the product is out of stock are
+/// [`PriceError::Unavailable`].
+pub fn extract_snapshot(
+    document: &Html,
+    source: Option<&dyn PriceSource>,
+) -> Result<Option<PriceSnapshot>, PriceError> {
+    let sources = source.map_or(retailers::PRICE_SOURCES.to_vec(), |source| vec![source]);
+    let source_availability = || {
+        sources
+            .iter()
+            .find_map(|source| source.extract_availability(document))
+    };
+    let availability = if source.is_some_and(|source| source.overrides_structured_data()) {
+        source_availability().or_else(|| structured_data::extract_availability(document))
+    } else {
+        structured_data::extract_availability(document).or_else(source_availability)
+    };
+    let Some(price) = extract_price(document, source)? else {
+        return match availability {
+            Some(Availability::OutOfStock) => Err(PriceError::Unavailable),
+            _ => Ok(None),
+        };
+    };
+
+    let mut snapshot = PriceSnapshot::new(price);
+    snapshot.availability = availability.unwrap_or(Availability::InStock);
+    snapshot.unit_price = sources
+        .iter()
+        .find_map(|source| source.extract_unit_price(document));
+    snapshot.pack_size = sources
+        .iter()
+        .find_map(|source| source.extract_pack_size(document));
+    snapshot.promotion = sources
+        .iter()
+        .find_map(|source| source.extract_promotion(document));
+    if snapshot.unit_price.is_none() {
+        snapshot.unit_price = snapshot
+            .pack_size
+            .map(|pack_size| pack_size.unit_price(price));
+    }
+    Ok(Some(snapshot))
+}
+
+/// Extraction chain: structured data (JSON-LD, microdata, OpenGraph) first, so a retailer
+/// theme change doesn't silently break tracking, then the CSS selectors of `source`, or of
+/// every built-in adapter in turn when the host is unknown. Sources that
+/// [override structured data](PriceSource::overrides_structured_data) come first instead.
+pub fn extract_price(
+    document: &Html,
+    source: Option<&dyn PriceSource>,
+) -> Result<Option<Money>, PriceError> {
+    if let Some(source) = source.filter(|source| source.overrides_structured_data()) {
+        if let Some(price) = source.extract_price(document)? {
+            return Ok(Some(price));
+        }
+        return Ok(structured_data::extract_price(document));
+    }
+    if let Some(price) = structured_data::extract_price(document) {
+        return Ok(Some(price));
+    }
+
+    if let Some(source) = source {
+        return source.extract_price(document);
+    }
+    for source in retailers::PRICE_SOURCES {
+        if let Some(price) = source.extract_price(document)? {
+            return Ok(Some(price));
+        }
+    }
+    Ok(None)
+}
+
+pub async fn fetch_page(url: &str) -> Result<String, PriceError> {
+    let response = http::client().get(url).await?;
 
     if !response.status().is_success() {
         return Err(PriceError::RequestFailed(response.status()));
     }
 
+    Ok(response.text().await?)
+}
+
+/// A page fetched with [`fetch_page_cached`].
+#[derive(Debug, PartialEq)]
+pub enum Page {
+    Changed(String),
+    Unchanged,
+}
+
+/// Fetches the page at `url` as [`fetch_page`] does, using the cached validators and body
+/// hash of `url` according to `policy`, and reads it with `source`. The page that was read is
+/// returned along with the result, and the entry to cache when the page is worth caching.
+async fn fetch_page_cached(
+    url: &str,
+    policy: CachePolicy,
+    source: Option<&dyn PriceSource>,
+) -> (
+    Result<Option<PriceSnapshot>, PriceError>,
+    Option<String>,
+    Option<CacheEntry>,
+) {
+    let cached = match policy {
+        CachePolicy::Bypass => {
+            return match fetch_page(url).await {
+                Ok(body) => (
+                    extract_snapshot(&Html::parse_document(&body), source),
+                    Some(body),
+                    None,
+                ),
+                Err(err) => (Err(err), None, None),
+            }
+        }
+        CachePolicy::Refresh => None,
+        CachePolicy::Revalidate => http_cache::get_entry(url).await,
+    };
+    read_page(url, cached.as_ref(), source).await
+}
+
+/// Revalidates `url` against `cached` and reads the page with `source` when it changed. The
+/// entry is only returned when the page gave a price or said the product is unavailable, so
+/// pages the extractors failed on are read again by the next check instead of being
+/// [`PriceError::Unchanged`].
+async fn read_page(
+    url: &str,
+    cached: Option<&CacheEntry>,
+    source: Option<&dyn PriceSource>,
+) -> (
+    Result<Option<PriceSnapshot>, PriceError>,
+    Option<String>,
+    Option<CacheEntry>,
+) {
+    match revalidate_page(url, cached).await {
+        Ok((Page::Changed(body), entry)) => {
+            let result = extract_snapshot(&Html::parse_document(&body), source);
+            let entry =
+                entry.filter(|_| matches!(result, Ok(Some(_)) | Err(PriceError::Unavailable)));
+            (result, Some(body), entry)
+        }
+        Ok((Page::Unchanged, entry)) => (Err(PriceError::Unchanged), None, entry),
+        Err(err) => (Err(err), None, None),
+    }
+}
+
+/// Fetches `url` with the conditional headers of `cached`, returning the page along with the
+/// entry to cache when a body was received. Servers answering 304 and bodies hashing like the
+/// cached one are [`Page::Unchanged`].
+async fn revalidate_page(
+    url: &str,
+    cached: Option<&CacheEntry>,
+) -> Result<(Page, Option<CacheEntry>), PriceError> {
+    let headers = cached
+        .map(CacheEntry::conditional_headers)
+        .unwrap_or_default();
+    let response = http::client().get_with_headers(url, headers).await?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_MODIFIED && cached.is_some() {
+        return Ok((Page::Unchanged, None));
+    }
+    if !status.is_success() {
+        return Err(PriceError::RequestFailed(status));
+    }
+
+    let headers = response.headers().clone();
     let body = response.text().await?;
-    let document = Html::parse_document(&body);
-    let selector =
-        Selector::parse("span.ct-price-formatted").map_err(|_| PriceError::HtmlParseError)?;
+    let entry = CacheEntry::new(url, &headers, &body);
+    let page = match cached {
+        Some(cached) if cached.content_hash == entry.content_hash => Page::Unchanged,
+        _ => Page::Changed(body),
+    };
+    Ok((page, Some(entry)))
+}
 
-    if let Some(element) = document.select(&selector).next() {
-        let text = element.text().collect::<Vec<_>>().join("");
-        let text = text.trim();
-        let price_as_float = text
-            .trim_start_matches('€')
-            .replace(',', ".")
-            .parse::<f32>()?;
-        return Ok(Some(price_as_float));
+/// Queries the current price of `product` and records it in the price history, along with
+/// its availability and the health of its scraper. Pages that did not change since the last
+/// cached version are [`PriceError::Unchanged`] without being parsed.
+pub async fn fetch_price(
+    product: &Product,
+    policy: CachePolicy,
+) -> Result<Option<PriceSnapshot>, PriceError> {
+    let scraper = scrapers::find_scraper(&product.url);
+    let source = price_source(&product.url, scraper.as_deref());
+    let (result, body, entry) = fetch_page_cached(&product.url, policy, source).await;
+    if let Some(entry) = entry {
+        http_cache::store_entry(&entry).await;
     }
+    let outcome = CheckOutcome::classify(&result, body.as_deref());
+    if let Some(body) = &body {
+        let failed = matches!(outcome, CheckOutcome::Failure { .. });
+        page_snapshots::save(
+            product,
+            body,
+            &page_snapshots::describe_result(&result),
+            failed,
+        )
+        .await;
+    }
+    scraper_health::record_check(product, outcome).await;
+    match &result {
+        Ok(Some(snapshot)) => {
+            let source = source.map_or(product.retailer.as_str(), |source| source.name());
+            price_history::record_observation(product.id, snapshot, source).await;
+            stock::record_availability(product.id, snapshot.availability).await;
+        }
+        Err(PriceError::Unavailable) => {
+            stock::record_availability(product.id, Availability::OutOfStock).await;
+        }
+        _ => {}
+    }
+    result
+}
 
-    Ok(None)
+/// Availability learned from a price check, `None` when the check failed or found nothing.
+fn checked_availability(
+    result: &Result<Option<PriceSnapshot>, PriceError>,
+) -> Option<Availability> {
+    match result {
+        Ok(Some(snapshot)) => Some(snapshot.availability),
+        Err(PriceError::Unavailable) => Some(Availability::OutOfStock),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct PriceCheckerState {
+    last_price: Option<PriceSnapshot>,
+    last_error: Option<String>,
+    checks: u64,
+    unchanged: u64,
+}
+
+/// Service that periodically checks the price of one product and notifies its subscribers.
+pub struct PriceCheckerService {
+    product: Product,
+    schedule: Schedule,
+    state: Mutex<PriceCheckerState>,
 }
 
-pub async fn price_periodically_checker_thread(url: &str, sleep_interval: Duration) {
-    // log::info!(
-    //     "price_periodically_checker_thread started running with interval {}",
-    //     interval
-    // );
-    let mut last_price: f32;
-    let price_query = get_price(url).await;
-    match price_query {
-        Ok(price_option) => {
-            last_price = price_option.unwrap_or(0.0);
+impl PriceCheckerService {
+    pub fn new(product: Product, schedule: Schedule) -> Self {
+        Self {
+            product,
+            schedule,
+            state: Mutex::new(PriceCheckerState::default()),
         }
-        Err(error) => {
-            log::error!("Error querying price, setting to 0. Error: {}", error);
-            last_price = 0.0;
+    }
+
+    /// Fetches the price, keeping track of the outcome for [`BotService::status`]. Unchanged
+    /// pages are the last one that gave a price, so they clear the last error too.
+    async fn check(&self, policy: CachePolicy) -> Result<Option<PriceSnapshot>, PriceError> {
+        let result = fetch_price(&self.product, policy).await;
+        let mut state = self.state.lock().unwrap();
+        state.checks += 1;
+        match &result {
+            Ok(_) => state.last_error = None,
+            Err(PriceError::Unchanged) => {
+                state.unchanged += 1;
+                state.last_error = None;
+            }
+            Err(error) => state.last_error = Some(error.to_string()),
         }
+        result
     }
 
-    loop {
-        log::info!("Going into sleep..");
+    /// Runs [`Self::check`] and stores the outcome as a service run for `/status`.
+    async fn scheduled_check(
+        &self,
+        ctx: &ServiceContext,
+        policy: CachePolicy,
+    ) -> Result<Option<PriceSnapshot>, PriceError> {
+        let started_at = Utc::now();
+        let result = self.check(policy).await;
+        let run_result = match &result {
+            Ok(Some(_)) | Err(PriceError::Unavailable) | Err(PriceError::Unchanged) => Ok(()),
+            Ok(None) => Err("no price found on the page".to_string()),
+            Err(error) => Err(error.to_string()),
+        };
+        ctx.record_run(started_at, run_result).await;
+        result
+    }
 
-        sleep(sleep_interval).await;
+    async fn check_periodically(&self, ctx: ServiceContext) {
+        let product = &self.product;
+        log::info!("Price checker for '{}' started running", &product.name);
+        let mut last_price: Option<PriceSnapshot> = price_history::latest_observation(product.id)
+            .await
+            .map(|observation| observation.snapshot());
+        let mut last_availability = stock::latest_availability(product.id).await;
 
-        log::info!("Checking milk price again..");
-        let current_price_res = get_price(url).await;
+        if last_price.is_none() {
+            // Nothing was read from the page yet, even if an earlier product cached it.
+            let result = self.scheduled_check(&ctx, CachePolicy::Refresh).await;
+            last_availability = checked_availability(&result).or(last_availability);
+            match result {
+                Ok(price_option) => last_price = price_option,
+                Err(error) => log::error!("Error querying '{}' price: {}", &product.name, error),
+            }
+        }
+        self.state.lock().unwrap().last_price = last_price.clone();
 
-        let current_price: f32 = match current_price_res {
-            Ok(price_option) => {
-                if price_option.is_none() {
+        loop {
+            ctx.wait_for_next_run().await;
+
+            log::info!("Checking '{}' price again..", &product.name);
+            let result = self.scheduled_check(&ctx, CachePolicy::Revalidate).await;
+            let availability = checked_availability(&result);
+            if let Some((previous, current)) = last_availability.zip(availability) {
+                let snapshot = result.as_ref().ok().and_then(Option::as_ref);
+                if let Some(message) =
+                    stock::change_message(&product.name, previous, current, snapshot)
+                {
+                    chat::notify_subscribers(&product.name, &message).await;
+                }
+            }
+            last_availability = availability.or(last_availability);
+            if availability.is_some() {
+                groups::check_cheapest(product.id).await;
+            }
+
+            let current_price = match result {
+                Ok(Some(snapshot)) => snapshot,
+                Ok(None) => continue,
+                Err(PriceError::Unchanged) => {
+                    log::info!("'{}' page has not changed", &product.name);
+                    continue;
+                }
+                Err(error) => {
+                    log::error!("Error querying '{}' price: {}", &product.name, error);
                     continue;
                 }
-                price_option.unwrap()
+            };
+            self.state.lock().unwrap().last_price = Some(current_price.clone());
+
+            let Some(previous_price) = last_price else {
+                last_price = Some(current_price);
+                continue;
+            };
+
+            let promotion_message =
+                promotions::change_message(&product.name, &previous_price, &current_price);
+            if let Some(message) = &promotion_message {
+                chat::notify_subscribers(&product.name, message).await;
             }
-            Err(_) => continue,
-        };
+            if current_price.price != previous_price.price {
+                notify_price_change(
+                    product,
+                    &previous_price,
+                    &current_price,
+                    promotion_message.is_none(),
+                )
+                .await;
+            }
+            last_price = Some(current_price);
+        }
+    }
+}
+
+impl BotService for PriceCheckerService {
+    fn name(&self) -> &str {
+        &self.product.name
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Checks the {} price at {}",
+            &self.product.retailer, &self.product.url
+        )
+    }
+
+    fn run(self: Arc<Self>, ctx: ServiceContext) -> BoxFuture<'static, ()> {
+        Box::pin(async move { self.check_periodically(ctx).await })
+    }
+
+    fn schedule(&self) -> Option<Schedule> {
+        Some(self.schedule.clone())
+    }
+
+    fn on_command<'a>(&'a self, args: &'a str) -> BoxFuture<'a, Option<String>> {
+        Box::pin(async move {
+            if args.trim() != "check" {
+                return None;
+            }
+            Some(match self.check(CachePolicy::Bypass).await {
+                Ok(Some(snapshot)) => {
+                    format!("Current {} price is: {}", self.product.name, snapshot)
+                }
+                Ok(None) => format!("Could not find a price for {}.", self.product.name),
+                Err(PriceError::Unavailable) => format!("{} is out of stock.", self.product.name),
+                Err(err) => format!("Failed to query {} price: {}", self.product.name, err),
+            })
+        })
+    }
 
-        if current_price != last_price {
-            let value_increased = current_price > last_price;
-            let emoji = if value_increased { "😔" } else { "😊" };
-            let message = format!(
-                "Mimosa Protein Milk price went from {} to {}! 🥛🐄{}",
-                last_price, current_price, emoji
-            );
-            let _ = chat::send_message(&message).await;
-            last_price = current_price;
+    fn status(&self) -> Vec<(String, String)> {
+        let state = self.state.lock().unwrap();
+        let mut status = vec![
+            (
+                "last price".to_string(),
+                state
+                    .last_price
+                    .as_ref()
+                    .map_or("unknown".to_string(), |snapshot| snapshot.to_string()),
+            ),
+            ("checks".to_string(), state.checks.to_string()),
+            ("unchanged pages".to_string(), state.unchanged.to_string()),
+        ];
+        if let Some(error) = &state.last_error {
+            status.push(("last error".to_string(), error.clone()));
         }
+        status
+    }
+}
+
+/// Notifies on every price change unless the product has alerts, in which case only the
+/// alerts whose boundary was crossed are sent. `announce_change` is false when the change was
+/// already announced as a promotion starting or ending.
+async fn notify_price_change(
+    product: &Product,
+    previous_price: &PriceSnapshot,
+    current_price: &PriceSnapshot,
+    announce_change: bool,
+) {
+    let emoji = if current_price.price > previous_price.price {
+        "😔"
+    } else {
+        "😊"
+    };
+    let product_alerts = alerts::get_alerts(product.id).await;
+    let rates = exchange_rates::get_rates().await;
+
+    if product_alerts.is_empty() {
+        if !announce_change {
+            return;
+        }
+        let message = format!(
+            "{} price went from {} to {}! 🛒{}",
+            &product.name, previous_price, current_price, emoji
+        );
+        chat::notify_subscribers(&product.name, &message).await;
+        return;
+    }
+
+    for alert in product_alerts
+        .iter()
+        .filter(|alert| alert.is_triggered(previous_price, current_price, &rates))
+    {
+        let message = format!(
+            "🔔 {} price went from {} to {}, alert #{} ({}) triggered! {}",
+            &product.name, previous_price, current_price, alert.id, alert, emoji
+        );
+        chat::notify_subscribers(&product.name, &message).await;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    
 
     #[tokio::test]
     async fn test_get_price_success() {
@@ -108,11 +588,49 @@ mod tests {
         let mut url = server.url();
         url.push_str("/produto/leite-proteina-sem-lactose-mimosa-7652960.html");
 
-        let result = get_price(&url).await.unwrap();
-        assert_eq!(result, Some(1.29));
+        let result = get_snapshot(&url).await.unwrap();
+        assert_eq!(result.map(|snapshot| snapshot.price), Some(Money::eur(129)));
         mock.assert()
     }
 
+    #[tokio::test]
+    async fn test_get_price_structured_data_fallback() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock(
+                "GET",
+                "/produto/leite-proteina-sem-lactose-mimosa-7652960.html",
+            )
+            .with_status(200)
+            .with_body(
+                "<script type=\"application/ld+json\">\
+                {\"@type\": \"Product\", \"offers\": {\"price\": \"1.35\"}}</script>\
+                <span class=\"new-theme-price\">€1,35</span>",
+            )
+            .create();
+
+        let mut url = server.url();
+        url.push_str("/produto/leite-proteina-sem-lactose-mimosa-7652960.html");
+
+        let result = get_snapshot(&url).await.unwrap();
+        assert_eq!(result.map(|snapshot| snapshot.price), Some(Money::eur(135)));
+        mock.assert()
+    }
+
+    #[test]
+    fn test_unit_price_from_pack_size() {
+        let document = Html::parse_document(
+            "<script type=\"application/ld+json\">\
+            {\"@type\": \"Product\", \"offers\": {\"price\": \"5.94\"}}</script>\
+            <span class=\"ct-pdp--unit\">emb. 6 x 1 lt</span>",
+        );
+        let snapshot = extract_snapshot(&document, Some(&retailers::Continente))
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.price, Money::eur(594));
+        assert_eq!(snapshot.to_string(), "5,94 € for 6 L (0,99 €/L)");
+    }
+
     #[tokio::test]
     async fn test_get_price_parse_error() {
         let mut server = mockito::Server::new_async().await;
@@ -127,7 +645,7 @@ mod tests {
 
         let mut url = server.url();
         url.push_str("/produto/leite-proteina-sem-lactose-mimosa-7652960.html");
-        let result = get_price(&url).await;
+        let result = get_snapshot(&url).await;
         assert!(matches!(result, Err(PriceError::PriceParseError(_))));
         mock.assert()
     }
@@ -145,8 +663,92 @@ mod tests {
 
         let mut url = server.url();
         url.push_str("/produto/leite-proteina-sem-lactose-mimosa-7652960.html");
-        let result = get_price(&url).await;
+        let result = get_snapshot(&url).await;
         assert!(matches!(result, Err(PriceError::RequestFailed(_))));
         mock.assert()
     }
+
+    #[tokio::test]
+    async fn test_revalidate_not_modified() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/produto/leite.html")
+            .match_header("if-none-match", "\"v1\"")
+            .with_status(304)
+            .create();
+
+        let url = format!("{}/produto/leite.html", server.url());
+        let cached = CacheEntry {
+            url: url.clone(),
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+            content_hash: http_cache::content_hash("<span>€1,29</span>"),
+        };
+        let (page, entry) = revalidate_page(&url, Some(&cached)).await.unwrap();
+        assert_eq!(page, Page::Unchanged);
+        assert_eq!(entry, None);
+        mock.assert()
+    }
+
+    #[tokio::test]
+    async fn test_revalidate_content_hash() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/produto/leite.html")
+            .with_status(200)
+            .with_header("etag", "\"v2\"")
+            .with_body("<span>€1,29</span>")
+            .expect(3)
+            .create();
+
+        let url = format!("{}/produto/leite.html", server.url());
+        let (page, entry) = revalidate_page(&url, None).await.unwrap();
+        assert_eq!(page, Page::Changed("<span>€1,29</span>".to_string()));
+        let entry = entry.unwrap();
+        assert_eq!(entry.etag.as_deref(), Some("\"v2\""));
+
+        let (page, _) = revalidate_page(&url, Some(&entry)).await.unwrap();
+        assert_eq!(page, Page::Unchanged);
+
+        let stale = CacheEntry {
+            content_hash: http_cache::content_hash("<span>€1,35</span>"),
+            ..entry
+        };
+        let (page, entry) = revalidate_page(&url, Some(&stale)).await.unwrap();
+        assert!(matches!(page, Page::Changed(_)));
+        assert_eq!(
+            entry.unwrap().content_hash,
+            http_cache::content_hash("<span>€1,29</span>")
+        );
+        mock.assert()
+    }
+
+    #[tokio::test]
+    async fn test_read_page_after_failure() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/produto/leite.html")
+            .with_status(200)
+            .with_body("<span class=\"ct-price-formatted\">€1,29</span>")
+            .expect(3)
+            .create();
+
+        let url = format!("{}/produto/leite.html", server.url());
+        let pingo_doce: &dyn PriceSource = &retailers::PingoDoce;
+        let (result, body, entry) = read_page(&url, None, Some(pingo_doce)).await;
+        assert!(matches!(result, Ok(None)));
+        assert!(body.is_some());
+        assert_eq!(entry, None);
+
+        // Nothing was cached, so the same body is parsed again once the scraper is fixed.
+        let continente: &dyn PriceSource = &retailers::Continente;
+        let (result, _, entry) = read_page(&url, entry.as_ref(), Some(continente)).await;
+        assert_eq!(result.unwrap().unwrap().price, Money::eur(129));
+        assert!(entry.is_some());
+
+        let (result, body, _) = read_page(&url, entry.as_ref(), Some(continente)).await;
+        assert!(matches!(result, Err(PriceError::Unchanged)));
+        assert_eq!(body, None);
+        mock.assert()
+    }
 }
@@ -0,0 +1,265 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Sub;
+
+/// ISO 4217 currency code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const EUR: Self = Self(*b"EUR");
+
+    /// Parses a three letter code such as `EUR` or `usd`.
+    pub fn parse(code: &str) -> Option<Self> {
+        let code = code.trim().to_ascii_uppercase();
+        let bytes: [u8; 3] = code.as_bytes().try_into().ok()?;
+        bytes
+            .iter()
+            .all(u8::is_ascii_uppercase)
+            .then_some(Self(bytes))
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only built from ASCII letters.
+        std::str::from_utf8(&self.0).unwrap()
+    }
+
+    fn symbol(&self) -> &str {
+        match self.as_str() {
+            "EUR" => "€",
+            "GBP" => "£",
+            "USD" => "US$",
+            "BRL" => "R$",
+            code => code,
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An amount of money in cents, so prices compare and add up exactly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Money {
+    pub cents: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(cents: i64, currency: Currency) -> Self {
+        Self { cents, currency }
+    }
+
+    pub fn eur(cents: i64) -> Self {
+        Self::new(cents, Currency::EUR)
+    }
+
+    /// Parses amounts as displayed by Portuguese retailers, e.g. `€1,29`, `1,29 €`, `1.29`,
+    /// `1.234,56 €` or `1.234`. The last `,` or `.` is the decimal separator unless it appears
+    /// more than once, or once with exactly three digits after it and no other separator, in
+    /// which case it separates thousands. Digits past the cents are rounded.
+    pub fn parse(text: &str, currency: Currency) -> Option<Self> {
+        let (negative, amount) = Self::clean(text, currency)?;
+        let decimal_separator = match amount.rfind([',', '.']) {
+            Some(index) => {
+                let separator = amount[index..].chars().next()?;
+                let other = if separator == ',' { '.' } else { ',' };
+                let repeated = amount.matches(separator).count() > 1;
+                let thousands = amount.len() - index - 1 == 3 && !amount.contains(other);
+                if repeated || thousands {
+                    other
+                } else {
+                    separator
+                }
+            }
+            None => ',',
+        };
+        Self::parse_amount(negative, &amount, decimal_separator, currency)
+    }
+
+    /// Parses an amount whose decimal separator is known, the other one of `,` and `.`
+    /// separating thousands, e.g. JSON numbers or amounts read by a scraper definition.
+    pub fn parse_with_decimal(
+        text: &str,
+        decimal_separator: char,
+        currency: Currency,
+    ) -> Option<Self> {
+        let (negative, amount) = Self::clean(text, currency)?;
+        Self::parse_amount(negative, &amount, decimal_separator, currency)
+    }
+
+    /// Strips the currency, sign and spaces off `text`, leaving digits, `,` and `.`.
+    fn clean(text: &str, currency: Currency) -> Option<(bool, String)> {
+        let mut amount = text.trim();
+        for affix in ["€", currency.symbol(), currency.as_str()] {
+            amount = amount
+                .trim_start_matches(affix)
+                .trim_end_matches(affix)
+                .trim();
+        }
+        let (negative, amount) = match amount.strip_prefix('-') {
+            Some(amount) => (true, amount),
+            None => (false, amount),
+        };
+        let amount: String = amount
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '\u{a0}')
+            .collect();
+        if amount.is_empty()
+            || !amount
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == ',' || c == '.')
+        {
+            return None;
+        }
+        Some((negative, amount))
+    }
+
+    fn parse_amount(
+        negative: bool,
+        amount: &str,
+        decimal_separator: char,
+        currency: Currency,
+    ) -> Option<Self> {
+        let (integer, fraction) = amount.split_once(decimal_separator).unwrap_or((amount, ""));
+        if !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let integer: String = integer.chars().filter(char::is_ascii_digit).collect();
+        if integer.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        let units: i64 = if integer.is_empty() {
+            0
+        } else {
+            integer.parse().ok()?
+        };
+        let digit = |index: usize| {
+            fraction
+                .as_bytes()
+                .get(index)
+                .map_or(0, |digit| (digit - b'0') as i64)
+        };
+        let round_up = (digit(2) >= 5) as i64;
+        let cents = units.checked_mul(100)? + digit(0) * 10 + digit(1) + round_up;
+        Some(Self::new(if negative { -cents } else { cents }, currency))
+    }
+
+    /// Rounds `amount` to the nearest cent, for amounts computed or stored as floats.
+    pub fn from_f64(amount: f64, currency: Currency) -> Self {
+        Self::new((amount * 100.0).round() as i64, currency)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.cents as f64 / 100.0
+    }
+
+    /// Splits this amount in `quantity` parts, rounded to the cent.
+    pub fn divide(&self, quantity: f32) -> Self {
+        Self::new(
+            (self.cents as f64 / quantity as f64).round() as i64,
+            self.currency,
+        )
+    }
+}
+
+/// Amounts in different currencies don't compare.
+impl PartialOrd for Money {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (self.currency == other.currency).then(|| self.cents.cmp(&other.cents))
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        debug_assert_eq!(self.currency, other.currency);
+        Money::new(self.cents - other.cents, self.currency)
+    }
+}
+
+/// Formats like Portuguese retailers, e.g. `1,29 €` or `-0,10 €`.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.cents < 0 {
+            "-"
+        } else if f.sign_plus() && self.cents > 0 {
+            "+"
+        } else {
+            ""
+        };
+        let cents = self.cents.unsigned_abs();
+        write!(
+            f,
+            "{}{},{:02} {}",
+            sign,
+            cents / 100,
+            cents % 100,
+            self.currency.symbol()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let parse = |text| Money::parse(text, Currency::EUR);
+        assert_eq!(parse("€1,29"), Some(Money::eur(129)));
+        assert_eq!(parse("1,29 €"), Some(Money::eur(129)));
+        assert_eq!(parse("1.29"), Some(Money::eur(129)));
+        assert_eq!(parse("0,5"), Some(Money::eur(50)));
+        assert_eq!(parse("2"), Some(Money::eur(200)));
+        assert_eq!(parse("1.234,56 €"), Some(Money::eur(123456)));
+        assert_eq!(parse("1,234.56"), Some(Money::eur(123456)));
+        assert_eq!(parse("1,2950"), Some(Money::eur(130)));
+        assert_eq!(parse("1.234.567"), Some(Money::eur(123456700)));
+        assert_eq!(parse("1.234"), Some(Money::eur(123400)));
+        assert_eq!(parse("1,234"), Some(Money::eur(123400)));
+        assert_eq!(parse("1.234.567,89 €"), Some(Money::eur(123456789)));
+        assert_eq!(parse("1.234,5.6"), None);
+        assert_eq!(parse("-0,10"), Some(Money::eur(-10)));
+        assert_eq!(parse("€invalid"), None);
+        assert_eq!(parse(","), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn test_parse_with_decimal() {
+        let parse = |text, separator| Money::parse_with_decimal(text, separator, Currency::EUR);
+        assert_eq!(parse("1.295", '.'), Some(Money::eur(130)));
+        assert_eq!(parse("1,295", ','), Some(Money::eur(130)));
+        assert_eq!(parse("1.234,56", ','), Some(Money::eur(123456)));
+        assert_eq!(parse("1.234.567", ','), Some(Money::eur(123456700)));
+        assert_eq!(parse("1,2,3", ','), None);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Money::eur(129).to_string(), "1,29 €");
+        assert_eq!(Money::eur(5).to_string(), "0,05 €");
+        assert_eq!(Money::eur(-10).to_string(), "-0,10 €");
+        assert_eq!(format!("{:+}", Money::eur(10)), "+0,10 €");
+        let usd = Currency::parse("usd").unwrap();
+        assert_eq!(Money::new(250, usd).to_string(), "2,50 US$");
+        let chf = Currency::parse("CHF").unwrap();
+        assert_eq!(Money::new(250, chf).to_string(), "2,50 CHF");
+    }
+
+    #[test]
+    fn test_exact_arithmetic() {
+        assert_eq!(Money::from_f64(1.29, Currency::EUR), Money::eur(129));
+        assert_eq!(Money::eur(594).divide(6.0), Money::eur(99));
+        assert_eq!(Money::eur(129) - Money::eur(119), Money::eur(10));
+        assert!(Money::eur(119) < Money::eur(129));
+        let usd = Currency::parse("USD").unwrap();
+        assert_eq!(Money::eur(100).partial_cmp(&Money::new(100, usd)), None);
+        assert_eq!(Currency::parse("EURO"), None);
+    }
+}
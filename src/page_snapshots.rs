@@ -0,0 +1,293 @@
+use crate::db;
+use crate::milk_price::{self, PriceError, PriceSnapshot};
+use crate::products::Product;
+use crate::scrapers;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use scraper::Html;
+use sqlx::FromRow;
+use std::io::{self, Read, Write};
+use std::sync::OnceLock;
+
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+const DEFAULT_MAX_PER_PRODUCT: i64 = 20;
+
+/// Which fetched pages are stored in `page_snapshots`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotMode {
+    Off,
+    /// Only pages whose check failed to read a price.
+    Failures,
+    All,
+}
+
+impl SnapshotMode {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "failures" => Some(Self::Failures),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
+}
+
+/// What pages are kept and for how long, read from the environment by [`Self::from_env`].
+#[derive(Clone, Debug)]
+pub struct SnapshotConfig {
+    pub mode: SnapshotMode,
+    pub retention_days: i64,
+    pub max_per_product: i64,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            mode: SnapshotMode::Off,
+            retention_days: DEFAULT_RETENTION_DAYS,
+            max_per_product: DEFAULT_MAX_PER_PRODUCT,
+        }
+    }
+}
+
+impl SnapshotConfig {
+    /// Defaults overridden by `PAGE_SNAPSHOTS` (`off`, `failures` or `all`),
+    /// `PAGE_SNAPSHOT_RETENTION_DAYS` and `PAGE_SNAPSHOTS_PER_PRODUCT`, unparsable values being
+    /// ignored.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        let var = |name: &str| std::env::var(name).ok();
+        let positive = |name: &str| {
+            var(name)
+                .and_then(|value| value.parse::<i64>().ok())
+                .filter(|value| *value > 0)
+        };
+        if let Some(mode) = var("PAGE_SNAPSHOTS").and_then(|value| SnapshotMode::parse(&value)) {
+            config.mode = mode;
+        }
+        if let Some(retention_days) = positive("PAGE_SNAPSHOT_RETENTION_DAYS") {
+            config.retention_days = retention_days;
+        }
+        if let Some(max_per_product) = positive("PAGE_SNAPSHOTS_PER_PRODUCT") {
+            config.max_per_product = max_per_product;
+        }
+        config
+    }
+
+    pub fn should_save(&self, failed: bool) -> bool {
+        match self.mode {
+            SnapshotMode::Off => false,
+            SnapshotMode::Failures => failed,
+            SnapshotMode::All => true,
+        }
+    }
+}
+
+fn config() -> &'static SnapshotConfig {
+    static CONFIG: OnceLock<SnapshotConfig> = OnceLock::new();
+    CONFIG.get_or_init(SnapshotConfig::from_env)
+}
+
+/// A page stored by [`save`], its HTML still compressed.
+#[derive(Clone, Debug, FromRow)]
+pub struct StoredPage {
+    pub id: i64,
+    pub product: String,
+    pub url: String,
+    pub fetch_time: String,
+    /// What the check that fetched the page read from it, see [`describe_result`].
+    pub outcome: String,
+    pub html: Vec<u8>,
+}
+
+pub fn compress(html: &str) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(html.as_bytes())?;
+    encoder.finish()
+}
+
+pub fn decompress(bytes: &[u8]) -> io::Result<String> {
+    let mut html = String::new();
+    GzDecoder::new(bytes).read_to_string(&mut html)?;
+    Ok(html)
+}
+
+/// One line summary of what a check read from a page.
+pub fn describe_result(result: &Result<Option<PriceSnapshot>, PriceError>) -> String {
+    match result {
+        Ok(Some(snapshot)) => snapshot.to_string(),
+        Ok(None) => "no price found".to_string(),
+        Err(err) => err.to_string(),
+    }
+}
+
+/// Stores `html` fetched for `product` when the configured mode keeps it, then drops the
+/// snapshots of `product` past the retention limits.
+pub async fn save(product: &Product, html: &str, outcome: &str, failed: bool) {
+    let config = config();
+    if !config.should_save(failed) {
+        return;
+    }
+    let compressed = match compress(html) {
+        Ok(compressed) => compressed,
+        Err(err) => {
+            log::error!("Failed to compress page of '{}'! {:?}", product.name, err);
+            return;
+        }
+    };
+
+    let db = db::get_db().await;
+    let insert_result = sqlx::query(
+        "INSERT INTO page_snapshots (product_id, url, outcome, html) VALUES (?, ?, ?, ?)",
+    )
+    .bind(product.id)
+    .bind(&product.url)
+    .bind(outcome)
+    .bind(compressed)
+    .execute(&db)
+    .await;
+    if let Err(err) = insert_result {
+        log::error!("Failed to store page of '{}'! {:?}", product.name, err);
+        return;
+    }
+
+    let prune_result = sqlx::query(
+        "DELETE FROM page_snapshots WHERE product_id = ?
+         AND (fetch_time < datetime('now', ?) OR id NOT IN (
+             SELECT id FROM page_snapshots WHERE product_id = ? ORDER BY id DESC LIMIT ?))",
+    )
+    .bind(product.id)
+    .bind(format!("-{} days", config.retention_days))
+    .bind(product.id)
+    .bind(config.max_per_product)
+    .execute(&db)
+    .await;
+    if let Err(err) = prune_result {
+        log::error!("Failed to prune pages of '{}'! {:?}", product.name, err);
+    }
+}
+
+/// Stored pages of the product with `name`, or of every product, oldest first.
+pub async fn get_pages(name: Option<&str>) -> Vec<StoredPage> {
+    let db = db::get_db().await;
+    let pages_query = sqlx::query_as::<_, StoredPage>(
+        "SELECT s.id, COALESCE(p.name, '#' || s.product_id) AS product, s.url, s.fetch_time,
+                s.outcome, s.html
+         FROM page_snapshots s LEFT JOIN products p ON p.id = s.product_id
+         WHERE ? IS NULL OR p.name = ?
+         ORDER BY s.id",
+    )
+    .bind(name)
+    .bind(name)
+    .fetch_all(&db)
+    .await;
+
+    match pages_query {
+        Ok(pages) => pages,
+        Err(err) => {
+            log::error!("Failed to query stored pages! {:?}", err);
+            Vec::new()
+        }
+    }
+}
+
+/// What the current extractors read from `page`, picked by its URL like a live check.
+pub fn replay_page(page: &StoredPage) -> io::Result<String> {
+    let html = decompress(&page.html)?;
+    let document = Html::parse_document(&html);
+    let scraper = scrapers::find_scraper(&page.url);
+    let result = milk_price::extract_snapshot(
+        &document,
+        milk_price::price_source(&page.url, scraper.as_deref()),
+    );
+    Ok(describe_result(&result))
+}
+
+/// Re-runs the current extractors against the stored pages of the product with `name`, or of
+/// every product, printing the outcome at the time next to the current one. Returns how many
+/// pages now give a different outcome.
+pub async fn replay(name: Option<&str>) -> usize {
+    let pages = get_pages(name).await;
+    let mut changed = 0;
+    for page in &pages {
+        let current = match replay_page(page) {
+            Ok(current) => current,
+            Err(err) => {
+                println!(
+                    "#{} {}: unreadable snapshot ({})",
+                    page.id, page.product, err
+                );
+                continue;
+            }
+        };
+        let marker = if current == page.outcome {
+            "="
+        } else {
+            changed += 1;
+            "≠"
+        };
+        println!(
+            "#{} {} at {}: {} {} {}",
+            page.id, page.product, page.fetch_time, page.outcome, marker, current
+        );
+    }
+    println!(
+        "Replayed {} pages, {} with a different outcome.",
+        pages.len(),
+        changed
+    );
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let html = "<span class=\"ct-price-formatted\">€1,29</span>".repeat(100);
+        let compressed = compress(&html).unwrap();
+        assert!(compressed.len() < html.len() / 10);
+        assert_eq!(decompress(&compressed).unwrap(), html);
+        assert!(decompress(b"not gzip").is_err());
+    }
+
+    #[test]
+    fn test_should_save() {
+        let config = |mode| SnapshotConfig {
+            mode,
+            ..SnapshotConfig::default()
+        };
+        assert!(!config(SnapshotMode::Off).should_save(true));
+        assert!(config(SnapshotMode::Failures).should_save(true));
+        assert!(!config(SnapshotMode::Failures).should_save(false));
+        assert!(config(SnapshotMode::All).should_save(false));
+        assert_eq!(
+            SnapshotMode::parse(" Failures"),
+            Some(SnapshotMode::Failures)
+        );
+        assert_eq!(SnapshotMode::parse("some"), None);
+    }
+
+    #[test]
+    fn test_replay_page() {
+        let page = |html: &str| StoredPage {
+            id: 1,
+            product: "mimosa".to_string(),
+            url: "https://www.continente.pt/produto/leite-mimosa-7652960.html".to_string(),
+            fetch_time: "2026-10-01 10:00:00".to_string(),
+            outcome: "no price found".to_string(),
+            html: compress(html).unwrap(),
+        };
+        assert_eq!(
+            replay_page(&page("<span class=\"ct-price-formatted\">€1,29</span>")).unwrap(),
+            describe_result(&Ok(Some(PriceSnapshot::new(Money::eur(129)))))
+        );
+        assert_eq!(
+            replay_page(&page("<p>new layout</p>")).unwrap(),
+            "no price found"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use crate::db;
+use crate::milk_price::PriceSnapshot;
+use crate::money::{Currency, Money};
+use crate::promotions::Promotion;
+use crate::stock::Availability;
+use crate::units::{PackSize, Unit, UnitPrice};
+use chrono::NaiveDate;
+use sqlx::{FromRow, SqlitePool};
+
+#[derive(Clone, FromRow, Debug)]
+pub struct PriceObservation {
+    pub id: i64,
+    pub product_id: i64,
+    pub timestamp: String,
+    pub price_cents: i64,
+    pub currency: String,
+    pub source: String,
+    pub unit_price_cents: Option<i64>,
+    pub unit: Option<String>,
+    pub pack_quantity: Option<f32>,
+    pub pack_unit: Option<String>,
+    pub regular_price_cents: Option<i64>,
+    pub promo_label: Option<String>,
+    pub promo_valid_from: Option<String>,
+    pub promo_valid_until: Option<String>,
+}
+
+impl PriceObservation {
+    /// Observations are stored with a valid currency, EUR is only a fallback for hand edits.
+    fn currency(&self) -> Currency {
+        Currency::parse(&self.currency).unwrap_or(Currency::EUR)
+    }
+
+    pub fn price(&self) -> Money {
+        Money::new(self.price_cents, self.currency())
+    }
+
+    /// The observation as read from the page, skipping unit columns with an unknown unit.
+    /// Availability isn't stored with observations, see [`crate::stock`], so it's in stock.
+    pub fn snapshot(&self) -> PriceSnapshot {
+        let unit = |unit: &Option<String>| unit.as_deref().and_then(Unit::from_stored);
+        let date = |date: &Option<String>| {
+            date.as_deref()
+                .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        };
+        let money = |cents: i64| Money::new(cents, self.currency());
+        PriceSnapshot {
+            price: self.price(),
+            unit_price: self
+                .unit_price_cents
+                .zip(unit(&self.unit))
+                .map(|(cents, unit)| UnitPrice {
+                    price: money(cents),
+                    unit,
+                }),
+            pack_size: self
+                .pack_quantity
+                .zip(unit(&self.pack_unit))
+                .map(|(quantity, unit)| PackSize { quantity, unit }),
+            promotion: (self.regular_price_cents.is_some() || self.promo_label.is_some()).then(
+                || Promotion {
+                    regular_price: self.regular_price_cents.map(money),
+                    label: self.promo_label.clone(),
+                    valid_from: date(&self.promo_valid_from),
+                    valid_until: date(&self.promo_valid_until),
+                },
+            ),
+            availability: Availability::InStock,
+        }
+    }
+
+    /// EUR observation of product 1 without unit or promotion, for tests.
+    #[cfg(test)]
+    pub fn test(timestamp: &str, price_cents: i64) -> Self {
+        Self {
+            id: 0,
+            product_id: 1,
+            timestamp: timestamp.to_string(),
+            price_cents,
+            currency: "EUR".to_string(),
+            source: "continente".to_string(),
+            unit_price_cents: None,
+            unit: None,
+            pack_quantity: None,
+            pack_unit: None,
+            regular_price_cents: None,
+            promo_label: None,
+            promo_valid_from: None,
+            promo_valid_until: None,
+        }
+    }
+}
+
+pub async fn record_observation(product_id: i64, snapshot: &PriceSnapshot, source: &str) {
+    let db = db::get_db().await;
+    let promotion = snapshot.promotion.as_ref();
+    let insert_result = sqlx::query(
+        "INSERT INTO price_observations
+         (product_id, price_cents, currency, source, unit_price_cents, unit, pack_quantity,
+          pack_unit, regular_price_cents, promo_label, promo_valid_from, promo_valid_until)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(product_id)
+    .bind(snapshot.price.cents)
+    .bind(snapshot.price.currency.as_str())
+    .bind(source)
+    .bind(snapshot.unit_price.map(|unit_price| unit_price.price.cents))
+    .bind(
+        snapshot
+            .unit_price
+            .map(|unit_price| unit_price.unit.as_str()),
+    )
+    .bind(snapshot.pack_size.map(|pack_size| pack_size.quantity))
+    .bind(snapshot.pack_size.map(|pack_size| pack_size.unit.as_str()))
+    .bind(promotion.and_then(|promotion| promotion.regular_price.map(|price| price.cents)))
+    .bind(promotion.and_then(|promotion| promotion.label.as_deref()))
+    .bind(promotion.and_then(|promotion| promotion.valid_from.map(|date| date.to_string())))
+    .bind(promotion.and_then(|promotion| promotion.valid_until.map(|date| date.to_string())))
+    .execute(&db)
+    .await;
+
+    if let Err(err) = insert_result {
+        log::error!(
+            "Insert of price observation for product {} failed! {:?}",
+            product_id,
+            err
+        );
+    }
+}
+
+pub async fn latest_observation(product_id: i64) -> Option<PriceObservation> {
+    let db = db::get_db().await;
+    let observation_query = sqlx::query_as::<_, PriceObservation>(
+        "SELECT * FROM price_observations WHERE product_id = ? ORDER BY timestamp DESC, id DESC LIMIT 1",
+    )
+    .bind(product_id)
+    .fetch_optional(&db)
+    .await;
+
+    match observation_query {
+        Ok(observation) => observation,
+        Err(err) => {
+            log::error!(
+                "Failed to query latest observation for product {}! {:?}",
+                product_id,
+                err
+            );
+            None
+        }
+    }
+}
+
+/// Observations of `product_id` from the last `seconds`, or all of them when `None`, oldest
+/// first.
+pub async fn get_observations(product_id: i64, seconds: Option<i64>) -> Vec<PriceObservation> {
+    let db = db::get_db().await;
+    query_observations(&db, product_id, seconds).await
+}
+
+pub async fn query_observations(
+    db: &SqlitePool,
+    product_id: i64,
+    seconds: Option<i64>,
+) -> Vec<PriceObservation> {
+    let since = seconds.map(|seconds| format!("-{} seconds", seconds));
+    let observations_query = sqlx::query_as::<_, PriceObservation>(
+        "SELECT * FROM price_observations
+         WHERE product_id = ?1 AND (?2 IS NULL OR timestamp >= datetime('now', ?2))
+         ORDER BY timestamp, id",
+    )
+    .bind(product_id)
+    .bind(since)
+    .fetch_all(db)
+    .await;
+
+    match observations_query {
+        Ok(observations) => observations,
+        Err(err) => {
+            log::error!(
+                "Failed to query observations of product {}! {:?}",
+                product_id,
+                err
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// In-memory database holding `observations`, on one connection as each opens its own.
+#[cfg(test)]
+pub async fn test_db(observations: &[PriceObservation]) -> SqlitePool {
+    let db = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::query(include_str!("../db/creation.sql"))
+        .execute(&db)
+        .await
+        .unwrap();
+    for observation in observations {
+        sqlx::query(
+            "INSERT INTO price_observations (product_id, timestamp, price_cents, currency, source)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(observation.product_id)
+        .bind(&observation.timestamp)
+        .bind(observation.price_cents)
+        .bind(&observation.currency)
+        .bind(&observation.source)
+        .execute(&db)
+        .await
+        .unwrap();
+    }
+    db
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    #[tokio::test]
+    async fn test_query_observations() {
+        let days_ago = |days| {
+            (Utc::now() - Duration::days(days))
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        };
+        let db = test_db(&[
+            PriceObservation::test("1999-12-31 10:00:00", 99),
+            PriceObservation::test(&days_ago(400), 119),
+            PriceObservation::test(&days_ago(2), 129),
+        ])
+        .await;
+
+        let prices = |observations: Vec<PriceObservation>| {
+            observations
+                .iter()
+                .map(|observation| observation.price_cents)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            prices(query_observations(&db, 1, None).await),
+            vec![99, 119, 129]
+        );
+        assert_eq!(
+            prices(query_observations(&db, 1, Some(30 * 24 * 60 * 60)).await),
+            vec![129]
+        );
+        assert!(query_observations(&db, 2, None).await.is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::db;
+use crate::money::Currency;
+use reqwest::Url;
+use sqlx::FromRow;
+
+#[derive(Clone, FromRow, Debug)]
+pub struct Product {
+    pub id: i64,
+    pub name: String,
+    pub url: String,
+    pub retailer: String,
+    pub currency: String,
+    pub creation_time: String,
+}
+
+impl Product {
+    /// Currency the retailer prices the product in, EUR for unknown codes.
+    pub fn currency(&self) -> Currency {
+        Currency::parse(&self.currency).unwrap_or(Currency::EUR)
+    }
+}
+
+pub async fn get_products() -> Vec<Product> {
+    let db = db::get_db().await;
+
+    let products_query = sqlx::query_as::<_, Product>("SELECT * FROM products ORDER BY id")
+        .fetch_all(&db)
+        .await;
+
+    match products_query {
+        Ok(products) => products,
+        Err(err) => {
+            log::error!("Failed to query tracked products! {:?}", err);
+            Vec::new()
+        }
+    }
+}
+
+pub async fn get_product(name: &str) -> Option<Product> {
+    let db = db::get_db().await;
+
+    let product_query = sqlx::query_as::<_, Product>("SELECT * FROM products WHERE name = ?")
+        .bind(name)
+        .fetch_optional(&db)
+        .await;
+
+    match product_query {
+        Ok(product) => product,
+        Err(err) => {
+            log::error!("Failed to query product with name: {}! {:?}", name, err);
+            None
+        }
+    }
+}
+
+pub async fn get_product_by_id(id: i64) -> Option<Product> {
+    let db = db::get_db().await;
+
+    let product_query = sqlx::query_as::<_, Product>("SELECT * FROM products WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&db)
+        .await;
+
+    match product_query {
+        Ok(product) => product,
+        Err(err) => {
+            log::error!("Failed to query product with id: {}! {:?}", id, err);
+            None
+        }
+    }
+}
+
+pub async fn add_product(
+    name: &str,
+    url: &str,
+    retailer: &str,
+    currency: Currency,
+) -> Result<Product, sqlx::Error> {
+    let db = db::get_db().await;
+    sqlx::query("INSERT INTO products (name, url, retailer, currency) VALUES (?, ?, ?, ?)")
+        .bind(name)
+        .bind(url)
+        .bind(retailer)
+        .bind(currency.as_str())
+        .execute(&db)
+        .await?;
+
+    log::info!("Product '{}' is now tracked from {}", name, url);
+    sqlx::query_as::<_, Product>("SELECT * FROM products WHERE name = ?")
+        .bind(name)
+        .fetch_one(&db)
+        .await
+}
+
+/// Deletes the product with `name`, returning whether it existed.
+pub async fn remove_product(name: &str) -> Result<bool, sqlx::Error> {
+    let db = db::get_db().await;
+    let delete_result = sqlx::query("DELETE FROM products WHERE name = ?")
+        .bind(name)
+        .execute(&db)
+        .await?;
+
+    Ok(delete_result.rows_affected() > 0)
+}
+
+/// Derives a retailer name from the URL host, e.g. `www.continente.pt` becomes `continente`.
+pub fn retailer_from_url(url: &Url) -> Option<String> {
+    let host = url.host_str()?.trim_start_matches("www.");
+    let retailer = host.split('.').next()?;
+    if retailer.is_empty() {
+        return None;
+    }
+    Some(retailer.to_lowercase())
+}
+
+/// Derives a product name from the last URL path segment, dropping the extension and the
+/// trailing product code, e.g. `.../leite-mimosa-7652960.html` becomes `leite_mimosa`.
+pub fn product_name_from_url(url: &Url) -> Option<String> {
+    let segment = url
+        .path_segments()?
+        .rev()
+        .find(|segment| !segment.is_empty())?;
+    let stem = segment.split('.').next().unwrap_or(segment);
+    let words: Vec<&str> = stem.split('-').filter(|word| !word.is_empty()).collect();
+    let words = match words.split_last() {
+        Some((last, rest)) if !rest.is_empty() && last.chars().all(|c| c.is_ascii_digit()) => rest,
+        _ => &words[..],
+    };
+    if words.is_empty() {
+        return None;
+    }
+    Some(words.join("_").to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retailer_from_url() {
+        let url = Url::parse("https://www.continente.pt/produto/leite-7652960.html").unwrap();
+        assert_eq!(retailer_from_url(&url), Some("continente".to_string()));
+
+        let url = Url::parse("https://auchan.pt/pt/leite/123.html").unwrap();
+        assert_eq!(retailer_from_url(&url), Some("auchan".to_string()));
+    }
+
+    #[test]
+    fn test_product_name_from_url() {
+        let url = Url::parse(
+            "https://www.continente.pt/produto/leite-proteina-sem-lactose-mimosa-7652960.html",
+        )
+        .unwrap();
+        assert_eq!(
+            product_name_from_url(&url),
+            Some("leite_proteina_sem_lactose_mimosa".to_string())
+        );
+
+        let url = Url::parse("https://www.pingodoce.pt/produtos/leite-meio-gordo/").unwrap();
+        assert_eq!(
+            product_name_from_url(&url),
+            Some("leite_meio_gordo".to_string())
+        );
+
+        let url = Url::parse("https://www.continente.pt/").unwrap();
+        assert_eq!(product_name_from_url(&url), None);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::milk_price::PriceSnapshot;
+use crate::money::Money;
+use crate::scheduler::DEFAULT_TIMEZONE;
+use chrono::{Datelike, NaiveDate, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::fmt;
+
+/// Matches dates such as `16/06` or `16/06/2024` in promotion validity texts.
+static DATE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(\d{1,2})[/.-](\d{1,2})(?:[/.-](\d{2,4}))?").unwrap());
+
+/// A discount running on a product, as advertised on its page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Promotion {
+    /// Price without the discount, shown struck through next to the promotional price.
+    pub regular_price: Option<Money>,
+    /// Badge text, e.g. `-25%` or `Cartão Continente`.
+    pub label: Option<String>,
+    pub valid_from: Option<NaiveDate>,
+    pub valid_until: Option<NaiveDate>,
+}
+
+impl Promotion {
+    /// Builds a promotion from what was found on the page, `None` when nothing hints at one.
+    pub fn new(
+        regular_price: Option<Money>,
+        label: Option<String>,
+        validity: Option<&str>,
+    ) -> Option<Self> {
+        let label = label
+            .map(|label| label.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|label| !label.is_empty());
+        if regular_price.is_none() && label.is_none() {
+            return None;
+        }
+        let today = Utc::now().with_timezone(&DEFAULT_TIMEZONE).date_naive();
+        let (valid_from, valid_until) =
+            validity.map_or((None, None), |text| parse_validity(text, today));
+        Some(Self {
+            regular_price,
+            label,
+            valid_from,
+            valid_until,
+        })
+    }
+}
+
+impl fmt::Display for Promotion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label.as_deref().unwrap_or("promotion"))?;
+        if let Some(regular_price) = self.regular_price {
+            write!(f, ", was {}", regular_price)?;
+        }
+        if let Some(valid_until) = self.valid_until {
+            write!(f, ", until {}", valid_until.format("%d/%m"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses validity texts such as `Válido de 10/06 a 16/06` or `até 16/06/2024`. A single date
+/// is the end date unless the text says it's a start (`desde`, `a partir de`). Dates without a
+/// year are taken in the year of `today`.
+pub fn parse_validity(text: &str, today: NaiveDate) -> (Option<NaiveDate>, Option<NaiveDate>) {
+    let dates: Vec<NaiveDate> = DATE
+        .captures_iter(text)
+        .filter_map(|captures| {
+            let day = captures[1].parse().ok()?;
+            let month = captures[2].parse().ok()?;
+            let year = match captures.get(3) {
+                Some(year) if year.as_str().len() == 2 => {
+                    2000 + year.as_str().parse::<i32>().ok()?
+                }
+                Some(year) => year.as_str().parse().ok()?,
+                None => today.year(),
+            };
+            NaiveDate::from_ymd_opt(year, month, day)
+        })
+        .collect();
+
+    match dates.as_slice() {
+        [] => (None, None),
+        [date] => {
+            let text = text.to_lowercase();
+            if text.contains("desde") || text.contains("a partir") {
+                (Some(*date), None)
+            } else {
+                (None, Some(*date))
+            }
+        }
+        [from, until, ..] => (Some(*from), Some(*until)),
+    }
+}
+
+/// Message announcing that a promotion of `name` started or ended between the two snapshots.
+pub fn change_message(
+    name: &str,
+    previous: &PriceSnapshot,
+    current: &PriceSnapshot,
+) -> Option<String> {
+    match (&previous.promotion, &current.promotion) {
+        (None, Some(promotion)) => Some(format!(
+            "🏷️ {} is on promotion: {} ({})! 🛒",
+            name, current.price, promotion
+        )),
+        (Some(_), None) => Some(format!(
+            "{} promotion ended, price went from {} to {}.",
+            name, previous.price, current
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_parse_validity() {
+        let today = date(2024, 6, 12);
+        assert_eq!(
+            parse_validity("Válido de 10/06 a 16/06", today),
+            (Some(date(2024, 6, 10)), Some(date(2024, 6, 16)))
+        );
+        assert_eq!(
+            parse_validity("Promoção válida até 30/06/2024", today),
+            (None, Some(date(2024, 6, 30)))
+        );
+        assert_eq!(
+            parse_validity("Desde 01.06.24", today),
+            (Some(date(2024, 6, 1)), None)
+        );
+        assert_eq!(
+            parse_validity("Enquanto durar o stock", today),
+            (None, None)
+        );
+        assert_eq!(parse_validity("até 31/02", today), (None, None));
+    }
+
+    #[test]
+    fn test_new_promotion() {
+        assert_eq!(Promotion::new(None, Some("  ".to_string()), None), None);
+
+        let promotion =
+            Promotion::new(Some(Money::eur(149)), Some(" -25%\n".to_string()), None).unwrap();
+        assert_eq!(promotion.label.as_deref(), Some("-25%"));
+        assert_eq!(promotion.to_string(), "-25%, was 1,49 €");
+    }
+
+    #[test]
+    fn test_change_message() {
+        let regular = PriceSnapshot::new(Money::eur(149));
+        let mut discounted = PriceSnapshot::new(Money::eur(112));
+        discounted.promotion = Some(Promotion {
+            regular_price: Some(Money::eur(149)),
+            label: Some("-25%".to_string()),
+            valid_from: None,
+            valid_until: Some(date(2024, 6, 16)),
+        });
+
+        assert_eq!(
+            change_message("milk", &regular, &discounted).as_deref(),
+            Some("🏷️ milk is on promotion: 1,12 € (-25%, was 1,49 €, until 16/06)! 🛒")
+        );
+        assert_eq!(
+            change_message("milk", &discounted, &regular).as_deref(),
+            Some("milk promotion ended, price went from 1,12 € to 1,49 €.")
+        );
+        assert_eq!(change_message("milk", &regular, &regular), None);
+        assert_eq!(change_message("milk", &discounted, &discounted), None);
+    }
+}
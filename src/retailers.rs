@@ -0,0 +1,366 @@
+use crate::milk_price::PriceError;
+use crate::money::{Currency, Money};
+use crate::promotions::Promotion;
+use crate::stock::Availability;
+use crate::units::{PackSize, UnitPrice};
+use reqwest::Url;
+use scraper::{Html, Selector};
+
+/// Extracts a product price from a retailer product page.
+pub trait PriceSource: Send + Sync {
+    /// Short retailer name, also stored as the source of each price observation.
+    fn name(&self) -> &str;
+    /// Whether this source knows how to read the page at `url`.
+    fn matches(&self, url: &Url) -> bool;
+    fn extract_price(&self, document: &Html) -> Result<Option<Money>, PriceError>;
+    /// Price per litre, kilogram or piece when the page shows it. Best effort, a missing or
+    /// unreadable unit price never fails a price check.
+    fn extract_unit_price(&self, _document: &Html) -> Option<UnitPrice> {
+        None
+    }
+    /// Pack size when the page shows it, used to compute the unit price when it isn't shown.
+    fn extract_pack_size(&self, _document: &Html) -> Option<PackSize> {
+        None
+    }
+    /// Promotion running on the product: struck through regular price, discount or
+    /// loyalty-card badge and validity dates. Best effort like the unit price.
+    fn extract_promotion(&self, _document: &Html) -> Option<Promotion> {
+        None
+    }
+    /// Availability shown by the retailer's own markup, when structured data doesn't have it.
+    fn extract_availability(&self, _document: &Html) -> Option<Availability> {
+        None
+    }
+    /// Whether the price and availability of this source are read before the page's
+    /// structured data, for sources configured on purpose for one shop.
+    fn overrides_structured_data(&self) -> bool {
+        false
+    }
+}
+
+pub struct Continente;
+pub struct PingoDoce;
+/// Salesforce-commerce style pages (Auchan and similar) that expose the numeric price in the
+/// `content` attribute of the price element.
+pub struct Auchan;
+
+pub static PRICE_SOURCES: [&dyn PriceSource; 3] = [&Continente, &PingoDoce, &Auchan];
+
+impl PriceSource for Continente {
+    fn name(&self) -> &str {
+        "continente"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        host_is(url, "continente.pt")
+    }
+
+    fn extract_price(&self, document: &Html) -> Result<Option<Money>, PriceError> {
+        select_text(document, "span.ct-price-formatted")?
+            .map(|text| parse_price_text(&text))
+            .transpose()
+    }
+
+    /// The secondary price reads like `€0,99 /lt` or `€4,50 /kg`.
+    fn extract_unit_price(&self, document: &Html) -> Option<UnitPrice> {
+        let text = select_text(document, ".pwc-tile--price-secondary").ok()??;
+        let (price, unit) = text.split_once('/')?;
+        UnitPrice::parse_per(parse_price_text(price).ok()?, unit)
+    }
+
+    fn extract_pack_size(&self, document: &Html) -> Option<PackSize> {
+        let text = select_text(document, ".ct-pdp--unit, .pwc-tile--quantity").ok()??;
+        PackSize::parse(&text)
+    }
+
+    fn extract_promotion(&self, document: &Html) -> Option<Promotion> {
+        select_promotion(
+            document,
+            ".pwc-tile--price-dashed",
+            ".pwc-discount-amount, .ct-product-tile-badge--card",
+            ".pwc-promo-dates",
+        )
+    }
+
+    fn extract_availability(&self, document: &Html) -> Option<Availability> {
+        out_of_stock_if(document, ".ct-product-unavailable, .pwc-tile--out-of-stock")
+    }
+}
+
+impl PriceSource for PingoDoce {
+    fn name(&self) -> &str {
+        "pingodoce"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        host_is(url, "pingodoce.pt")
+    }
+
+    fn extract_price(&self, document: &Html) -> Result<Option<Money>, PriceError> {
+        select_text(document, "div.product-price span.price")?
+            .map(|text| parse_price_text(&text))
+            .transpose()
+    }
+
+    fn extract_promotion(&self, document: &Html) -> Option<Promotion> {
+        select_promotion(
+            document,
+            "div.product-price span.old-price",
+            "span.discount-badge, span.poupa-mais-badge",
+            "span.promotion-validity",
+        )
+    }
+
+    fn extract_availability(&self, document: &Html) -> Option<Availability> {
+        out_of_stock_if(document, "div.product-unavailable")
+    }
+}
+
+impl PriceSource for Auchan {
+    fn name(&self) -> &str {
+        "auchan"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        host_is(url, "auchan.pt")
+    }
+
+    fn extract_price(&self, document: &Html) -> Result<Option<Money>, PriceError> {
+        let selector =
+            Selector::parse("span.sales span.value").map_err(|_| PriceError::HtmlParseError)?;
+        let Some(element) = document.select(&selector).next() else {
+            return Ok(None);
+        };
+
+        match element.value().attr("content") {
+            Some(content) => parse_price_text(content).map(Some),
+            None => parse_price_text(&element.text().collect::<String>()).map(Some),
+        }
+    }
+
+    fn extract_promotion(&self, document: &Html) -> Option<Promotion> {
+        select_promotion(
+            document,
+            "span.strike-through span.value",
+            "span.promotion-badge, span.auchan-card-badge",
+            "div.promotion-dates",
+        )
+    }
+
+    fn extract_availability(&self, document: &Html) -> Option<Availability> {
+        out_of_stock_if(
+            document,
+            "div.availability .out-of-stock, button.add-to-cart[disabled]",
+        )
+    }
+}
+
+/// Picks the price source for `url` by its host, `None` when no retailer adapter matches.
+pub fn source_for_url(url: &str) -> Option<&'static dyn PriceSource> {
+    let url = Url::parse(url).ok()?;
+    PRICE_SOURCES
+        .iter()
+        .copied()
+        .find(|source| source.matches(&url))
+}
+
+/// Whether `url` is served from `domain` or one of its subdomains.
+fn host_is(url: &Url, domain: &str) -> bool {
+    url.host_str()
+        .is_some_and(|host| host == domain || host.ends_with(&format!(".{}", domain)))
+}
+
+fn select_text(document: &Html, selector: &str) -> Result<Option<String>, PriceError> {
+    let selector = Selector::parse(selector).map_err(|_| PriceError::HtmlParseError)?;
+    Ok(document
+        .select(&selector)
+        .next()
+        .map(|element| element.text().collect::<String>()))
+}
+
+/// Out of stock when an element matching `selector` is present, unknown otherwise: product
+/// pages rarely mark being in stock.
+fn out_of_stock_if(document: &Html, selector: &str) -> Option<Availability> {
+    let selector = Selector::parse(selector).ok()?;
+    document
+        .select(&selector)
+        .next()
+        .map(|_| Availability::OutOfStock)
+}
+
+/// Reads a promotion from the struck through price, badge and validity elements, preferring
+/// the `content` attribute of the price like [`Auchan`] does.
+fn select_promotion(
+    document: &Html,
+    regular_price: &str,
+    label: &str,
+    validity: &str,
+) -> Option<Promotion> {
+    let selector = Selector::parse(regular_price).ok()?;
+    let regular_price = document.select(&selector).next().and_then(|element| {
+        match element.value().attr("content") {
+            Some(content) => parse_price_text(content).ok(),
+            None => parse_price_text(&element.text().collect::<String>()).ok(),
+        }
+    });
+    let label = select_text(document, label).ok()?;
+    let validity = select_text(document, validity).ok()?;
+    Promotion::new(regular_price, label, validity.as_deref())
+}
+
+/// Parses euro prices as displayed by Portuguese retailers, see [`Money::parse`].
+pub fn parse_price_text(text: &str) -> Result<Money, PriceError> {
+    Money::parse(text, Currency::EUR).ok_or_else(|| PriceError::PriceParseError(text.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::milk_price::get_snapshot_from;
+
+    const CONTINENTE_FIXTURE: &str = r#"
+        <div class="prices-wrapper">
+            <span class="ct-price-formatted">€1,29</span>
+            <span class="ct-m-unit">/un</span>
+        </div>"#;
+
+    const CONTINENTE_UNIT_PRICE_FIXTURE: &str = r#"
+        <div class="ct-pdp--unit">emb. 6 x 1 lt</div>
+        <div class="prices-wrapper">
+            <span class="ct-price-formatted">€5,94</span>
+            <span class="ct-m-unit">/un</span>
+        </div>
+        <div class="pwc-tile--price-secondary">
+            €0,99 <span class="pwc-m-unit">/lt</span>
+        </div>"#;
+
+    const CONTINENTE_PROMOTION_FIXTURE: &str = r#"
+        <div class="prices-wrapper">
+            <span class="pwc-discount-amount">-25%</span>
+            <span class="pwc-tile--price-dashed">€1,49</span>
+            <span class="ct-price-formatted">€1,12</span>
+            <span class="pwc-promo-dates">Válido de 10/06/2024 a 16/06/2024</span>
+        </div>"#;
+
+    const CONTINENTE_OUT_OF_STOCK_FIXTURE: &str = r#"
+        <div class="ct-product-unavailable">Produto esgotado</div>"#;
+
+    const PINGO_DOCE_FIXTURE: &str = r#"
+        <div class="product-price">
+            <span class="price">0,89 €</span>
+        </div>"#;
+
+    const AUCHAN_FIXTURE: &str = r#"
+        <div class="prices">
+            <span class="sales">
+                <span class="value" content="1.15">1,15 €</span>
+            </span>
+        </div>"#;
+
+    async fn serve_fixture(body: &str) -> (mockito::ServerGuard, mockito::Mock, String) {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/produto/leite.html")
+            .with_status(200)
+            .with_body(body)
+            .create();
+        let url = format!("{}/produto/leite.html", server.url());
+        (server, mock, url)
+    }
+
+    #[test]
+    fn test_source_for_url() {
+        let source = source_for_url("https://www.continente.pt/produto/leite.html").unwrap();
+        assert_eq!(source.name(), "continente");
+        let source = source_for_url("https://www.pingodoce.pt/produtos/leite/").unwrap();
+        assert_eq!(source.name(), "pingodoce");
+        let source = source_for_url("https://www.auchan.pt/pt/leite/123.html").unwrap();
+        assert_eq!(source.name(), "auchan");
+        assert!(source_for_url("https://notcontinente.pt/leite.html").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_continente_fixture() {
+        let (_server, mock, url) = serve_fixture(CONTINENTE_FIXTURE).await;
+        let result = get_snapshot_from(&url, Some(&Continente))
+            .await
+            .unwrap()
+            .map(|snapshot| snapshot.price);
+        assert_eq!(result, Some(Money::eur(129)));
+        mock.assert()
+    }
+
+    #[test]
+    fn test_continente_unit_price() {
+        let document = Html::parse_document(CONTINENTE_UNIT_PRICE_FIXTURE);
+        let unit_price = Continente.extract_unit_price(&document).unwrap();
+        assert_eq!(unit_price.to_string(), "0,99 €/L");
+        let pack_size = Continente.extract_pack_size(&document).unwrap();
+        assert_eq!(pack_size.to_string(), "6 L");
+
+        let document = Html::parse_document(CONTINENTE_FIXTURE);
+        assert!(Continente.extract_unit_price(&document).is_none());
+        assert!(Continente.extract_pack_size(&document).is_none());
+    }
+
+    #[test]
+    fn test_continente_promotion() {
+        let document = Html::parse_document(CONTINENTE_PROMOTION_FIXTURE);
+        let promotion = Continente.extract_promotion(&document).unwrap();
+        assert_eq!(promotion.regular_price, Some(Money::eur(149)));
+        assert_eq!(promotion.label.as_deref(), Some("-25%"));
+        assert_eq!(promotion.to_string(), "-25%, was 1,49 €, until 16/06");
+
+        let document = Html::parse_document(CONTINENTE_FIXTURE);
+        assert!(Continente.extract_promotion(&document).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_continente_out_of_stock() {
+        let document = Html::parse_document(CONTINENTE_OUT_OF_STOCK_FIXTURE);
+        assert_eq!(
+            Continente.extract_availability(&document),
+            Some(Availability::OutOfStock)
+        );
+        let document = Html::parse_document(CONTINENTE_FIXTURE);
+        assert_eq!(Continente.extract_availability(&document), None);
+
+        let (_server, mock, url) = serve_fixture(CONTINENTE_OUT_OF_STOCK_FIXTURE).await;
+        let result = get_snapshot_from(&url, Some(&Continente)).await;
+        assert!(matches!(result, Err(PriceError::Unavailable)));
+        mock.assert()
+    }
+
+    #[tokio::test]
+    async fn test_pingo_doce_fixture() {
+        let (_server, mock, url) = serve_fixture(PINGO_DOCE_FIXTURE).await;
+        let result = get_snapshot_from(&url, Some(&PingoDoce))
+            .await
+            .unwrap()
+            .map(|snapshot| snapshot.price);
+        assert_eq!(result, Some(Money::eur(89)));
+        mock.assert()
+    }
+
+    #[tokio::test]
+    async fn test_auchan_fixture() {
+        let (_server, mock, url) = serve_fixture(AUCHAN_FIXTURE).await;
+        let result = get_snapshot_from(&url, Some(&Auchan))
+            .await
+            .unwrap()
+            .map(|snapshot| snapshot.price);
+        assert_eq!(result, Some(Money::eur(115)));
+        mock.assert()
+    }
+
+    #[tokio::test]
+    async fn test_adapter_selector_miss() {
+        let (_server, mock, url) = serve_fixture(CONTINENTE_FIXTURE).await;
+        let result = get_snapshot_from(&url, Some(&PingoDoce))
+            .await
+            .unwrap()
+            .map(|snapshot| snapshot.price);
+        assert_eq!(result, None);
+        mock.assert()
+    }
+}
@@ -0,0 +1,388 @@
+use crate::db;
+use chrono::{DateTime, Duration as ChronoDuration, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+pub const DEFAULT_TIMEZONE: Tz = chrono_tz::Europe::Lisbon;
+
+/// Upper bound of cron occurrences inspected while looking for one inside the time window.
+const MAX_CRON_LOOKAHEAD: usize = 10_000;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ScheduleError {
+    #[error("Schedule must start with 'every <interval>' or 'cron <expression>'")]
+    MissingTrigger,
+    #[error("Invalid duration '{0}', use e.g. 30s, 15m, 4h or 1d")]
+    InvalidDuration(String),
+    #[error("Invalid cron expression '{0}'")]
+    InvalidCron(String),
+    #[error("Invalid time window '{0}', use e.g. 08:00-22:00")]
+    InvalidWindow(String),
+    #[error("Unknown timezone '{0}'")]
+    InvalidTimezone(String),
+    #[error("Unexpected '{0}' in schedule")]
+    UnexpectedToken(String),
+}
+
+#[derive(Clone, Debug)]
+pub enum Trigger {
+    Interval(ChronoDuration),
+    Cron(Box<cron::Schedule>),
+}
+
+/// Local time of day range, wrapping around midnight when `start` is after `end`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// When a service runs, written as e.g. `every 4h jitter 10m between 08:00-22:00` or
+/// `cron 0 8,20 * * * tz Europe/Lisbon`.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    pub trigger: Trigger,
+    pub jitter: ChronoDuration,
+    pub window: Option<TimeWindow>,
+    pub timezone: Tz,
+    spec: String,
+}
+
+impl Schedule {
+    pub fn every(interval: ChronoDuration) -> Self {
+        Self {
+            trigger: Trigger::Interval(interval),
+            jitter: ChronoDuration::zero(),
+            window: None,
+            timezone: DEFAULT_TIMEZONE,
+            spec: format!("every {}", format_duration(interval)),
+        }
+    }
+
+    pub fn parse(spec: &str) -> Result<Self, ScheduleError> {
+        let tokens: Vec<&str> = spec.split_whitespace().collect();
+        let mut tokens = tokens.iter().copied().peekable();
+
+        let trigger = match tokens.next().map(str::to_lowercase).as_deref() {
+            Some("every") => {
+                let interval = tokens.next().ok_or(ScheduleError::MissingTrigger)?;
+                Trigger::Interval(parse_duration(interval)?)
+            }
+            Some("cron") => {
+                let mut fields = vec![];
+                while let Some(field) = tokens.next_if(|token| !is_keyword(token)) {
+                    fields.push(field);
+                }
+                Trigger::Cron(Box::new(parse_cron(&fields.join(" "))?))
+            }
+            _ => return Err(ScheduleError::MissingTrigger),
+        };
+
+        let mut schedule = Self {
+            trigger,
+            jitter: ChronoDuration::zero(),
+            window: None,
+            timezone: DEFAULT_TIMEZONE,
+            spec: spec.split_whitespace().collect::<Vec<_>>().join(" "),
+        };
+        while let Some(keyword) = tokens.next() {
+            let value = tokens
+                .next()
+                .ok_or_else(|| ScheduleError::UnexpectedToken(keyword.to_string()))?;
+            match keyword.to_lowercase().as_str() {
+                "jitter" => schedule.jitter = parse_duration(value)?,
+                "between" => schedule.window = Some(parse_window(value)?),
+                "tz" => {
+                    schedule.timezone = Tz::from_str(value)
+                        .map_err(|_| ScheduleError::InvalidTimezone(value.to_string()))?
+                }
+                _ => return Err(ScheduleError::UnexpectedToken(keyword.to_string())),
+            }
+        }
+        Ok(schedule)
+    }
+
+    /// Next run strictly after `after`, delayed by `jitter_fraction` (in `[0, 1)`) of the
+    /// jitter and moved into the time window when there is one, so the delay never takes a run
+    /// past the end of the window.
+    pub fn next_run(&self, after: DateTime<Utc>, jitter_fraction: f64) -> DateTime<Utc> {
+        let jitter = ChronoDuration::milliseconds(
+            (self.jitter.num_milliseconds() as f64 * jitter_fraction) as i64,
+        );
+        match &self.trigger {
+            Trigger::Interval(interval) => self.move_into_window(after + *interval + jitter),
+            Trigger::Cron(cron) => cron
+                .after(&after.with_timezone(&self.timezone))
+                .take(MAX_CRON_LOOKAHEAD)
+                .map(|next| next.with_timezone(&Utc) + jitter)
+                .find(|next| self.in_window(*next))
+                .unwrap_or_else(|| self.move_into_window(after + ChronoDuration::days(1) + jitter)),
+        }
+    }
+
+    fn in_window(&self, time: DateTime<Utc>) -> bool {
+        self.window
+            .is_none_or(|window| window.contains(time.with_timezone(&self.timezone).time()))
+    }
+
+    /// Moves `time` forward to the next start of the window when it falls outside of it.
+    fn move_into_window(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let Some(window) = self.window else {
+            return time;
+        };
+        if self.in_window(time) {
+            return time;
+        }
+
+        let local = time.with_timezone(&self.timezone);
+        let mut date = local.date_naive();
+        if local.time() >= window.start {
+            date = date.succ_opt().unwrap_or(date);
+        }
+        self.timezone
+            .from_local_datetime(&date.and_time(window.start))
+            .earliest()
+            .map_or(time, |start| start.with_timezone(&Utc))
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+fn is_keyword(token: &str) -> bool {
+    matches!(token.to_lowercase().as_str(), "jitter" | "between" | "tz")
+}
+
+/// Parses durations such as `30s`, `15m`, `4h`, `1d` or `1h30m`.
+pub fn parse_duration(text: &str) -> Result<ChronoDuration, ScheduleError> {
+    let invalid = || ScheduleError::InvalidDuration(text.to_string());
+    let mut total = ChronoDuration::zero();
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value: i64 = number.parse().map_err(|_| invalid())?;
+        number.clear();
+        total += match c {
+            's' => ChronoDuration::seconds(value),
+            'm' => ChronoDuration::minutes(value),
+            'h' => ChronoDuration::hours(value),
+            'd' => ChronoDuration::days(value),
+            _ => return Err(invalid()),
+        };
+    }
+    if !number.is_empty() || total <= ChronoDuration::zero() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+fn format_duration(duration: ChronoDuration) -> String {
+    let seconds = duration.num_seconds();
+    match seconds {
+        s if s % 86_400 == 0 => format!("{}d", s / 86_400),
+        s if s % 3_600 == 0 => format!("{}h", s / 3_600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+/// Parses cron expressions, accepting the classic 5 field form by assuming second 0.
+fn parse_cron(expression: &str) -> Result<cron::Schedule, ScheduleError> {
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {}", expression),
+        _ => expression.to_string(),
+    };
+    cron::Schedule::from_str(&expression).map_err(|_| ScheduleError::InvalidCron(expression))
+}
+
+fn parse_window(text: &str) -> Result<TimeWindow, ScheduleError> {
+    let invalid = || ScheduleError::InvalidWindow(text.to_string());
+    let (start, end) = text.split_once('-').ok_or_else(invalid)?;
+    let start = NaiveTime::parse_from_str(start, "%H:%M").map_err(|_| invalid())?;
+    let end = NaiveTime::parse_from_str(end, "%H:%M").map_err(|_| invalid())?;
+    if start == end {
+        return Err(invalid());
+    }
+    Ok(TimeWindow { start, end })
+}
+
+/// Schedule stored for the service with `service_id`, `None` when it uses its default one.
+pub async fn get_schedule(service_id: i64) -> Option<Schedule> {
+    let db = db::get_db().await;
+    let spec_query =
+        sqlx::query_scalar::<_, String>("SELECT spec FROM schedules WHERE service_id = ?")
+            .bind(service_id)
+            .fetch_optional(&db)
+            .await;
+
+    match spec_query {
+        Ok(spec) => spec.and_then(|spec| match Schedule::parse(&spec) {
+            Ok(schedule) => Some(schedule),
+            Err(err) => {
+                log::error!("Ignoring schedule of service {}: {}", service_id, err);
+                None
+            }
+        }),
+        Err(err) => {
+            log::error!(
+                "Failed to query schedule of service {}! {:?}",
+                service_id,
+                err
+            );
+            None
+        }
+    }
+}
+
+pub async fn set_schedule(service_id: i64, schedule: &Schedule) -> Result<(), sqlx::Error> {
+    let db = db::get_db().await;
+    sqlx::query(
+        "INSERT INTO schedules (service_id, spec) VALUES (?, ?)
+         ON CONFLICT (service_id) DO UPDATE SET spec = excluded.spec,
+         update_time = CURRENT_TIMESTAMP",
+    )
+    .bind(service_id)
+    .bind(schedule.to_string())
+    .execute(&db)
+    .await?;
+    Ok(())
+}
+
+/// Forgets the stored schedule so the service goes back to its default one.
+pub async fn clear_schedule(service_id: i64) -> Result<(), sqlx::Error> {
+    let db = db::get_db().await;
+    sqlx::query("DELETE FROM schedules WHERE service_id = ?")
+        .bind(service_id)
+        .execute(&db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s"), Ok(ChronoDuration::seconds(30)));
+        assert_eq!(parse_duration("4h"), Ok(ChronoDuration::hours(4)));
+        assert_eq!(parse_duration("1h30m"), Ok(ChronoDuration::minutes(90)));
+        assert!(parse_duration("4").is_err());
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("4x").is_err());
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        let schedule = Schedule::parse("every 4h jitter 10m between 08:00-22:00").unwrap();
+        assert!(
+            matches!(schedule.trigger, Trigger::Interval(interval) if interval == ChronoDuration::hours(4))
+        );
+        assert_eq!(schedule.jitter, ChronoDuration::minutes(10));
+        assert_eq!(schedule.timezone, chrono_tz::Europe::Lisbon);
+        assert_eq!(
+            schedule.to_string(),
+            "every 4h jitter 10m between 08:00-22:00"
+        );
+
+        let schedule = Schedule::parse("cron 0 8,20 * * * tz UTC").unwrap();
+        assert!(matches!(schedule.trigger, Trigger::Cron(_)));
+        assert_eq!(schedule.timezone, chrono_tz::UTC);
+
+        assert_eq!(
+            Schedule::parse("daily").unwrap_err(),
+            ScheduleError::MissingTrigger
+        );
+        assert!(matches!(
+            Schedule::parse("every 4h tz Mars/Olympus"),
+            Err(ScheduleError::InvalidTimezone(_))
+        ));
+        assert!(matches!(
+            Schedule::parse("every 4h between 22:00"),
+            Err(ScheduleError::InvalidWindow(_))
+        ));
+        assert!(matches!(
+            Schedule::parse("cron not a cron"),
+            Err(ScheduleError::InvalidCron(_))
+        ));
+    }
+
+    #[test]
+    fn test_interval_next_run() {
+        let schedule = Schedule::every(ChronoDuration::hours(4));
+        let now = utc("2024-06-01T10:00:00Z");
+        assert_eq!(schedule.next_run(now, 0.0), utc("2024-06-01T14:00:00Z"));
+
+        let schedule = Schedule::parse("every 4h jitter 10m").unwrap();
+        assert_eq!(schedule.next_run(now, 0.5), utc("2024-06-01T14:05:00Z"));
+    }
+
+    #[test]
+    fn test_interval_moves_into_window() {
+        // Lisbon is UTC+1 in summer, so the window is 07:00-21:00 UTC.
+        let schedule = Schedule::parse("every 4h between 08:00-22:00").unwrap();
+        let now = utc("2024-06-01T19:00:00Z");
+        assert_eq!(schedule.next_run(now, 0.0), utc("2024-06-02T07:00:00Z"));
+
+        let now = utc("2024-06-01T02:00:00Z");
+        assert_eq!(schedule.next_run(now, 0.0), utc("2024-06-01T07:00:00Z"));
+
+        let now = utc("2024-06-01T10:00:00Z");
+        assert_eq!(schedule.next_run(now, 0.0), utc("2024-06-01T14:00:00Z"));
+    }
+
+    #[test]
+    fn test_cron_next_run_in_timezone_and_window() {
+        let schedule = Schedule::parse("cron 0 */6 * * * between 08:00-22:00").unwrap();
+        // 06:00 Lisbon falls outside the window, 12:00 Lisbon is the next one inside it.
+        let now = utc("2024-06-01T04:30:00Z");
+        assert_eq!(schedule.next_run(now, 0.0), utc("2024-06-01T11:00:00Z"));
+    }
+
+    #[test]
+    fn test_jitter_stays_in_window() {
+        // 21:50 Lisbon plus up to 30 minutes would end past 22:00.
+        let schedule = Schedule::parse("every 4h jitter 30m between 08:00-22:00").unwrap();
+        let now = utc("2024-06-01T16:50:00Z");
+        assert_eq!(schedule.next_run(now, 0.0), utc("2024-06-01T20:50:00Z"));
+        assert_eq!(schedule.next_run(now, 0.99), utc("2024-06-02T07:00:00Z"));
+
+        // 21:00 Lisbon plus 89 minutes is skipped for 09:00 Lisbon plus 89 minutes.
+        let schedule = Schedule::parse("cron 0 9,21 * * * jitter 90m between 08:00-22:00").unwrap();
+        let now = utc("2024-06-01T19:00:00Z");
+        assert_eq!(schedule.next_run(now, 0.0), utc("2024-06-01T20:00:00Z"));
+        assert_eq!(schedule.next_run(now, 0.99), utc("2024-06-02T09:29:06Z"));
+    }
+
+    #[test]
+    fn test_window_wrapping_midnight() {
+        let window = parse_window("22:00-06:00").unwrap();
+        assert!(window.contains(NaiveTime::from_hms_opt(23, 0, 0).unwrap()));
+        assert!(window.contains(NaiveTime::from_hms_opt(5, 59, 0).unwrap()));
+        assert!(!window.contains(NaiveTime::from_hms_opt(12, 0, 0).unwrap()));
+    }
+}
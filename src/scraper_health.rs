@@ -0,0 +1,325 @@
+use crate::chat;
+use crate::constants;
+use crate::db;
+use crate::milk_price::{PriceError, PriceSnapshot};
+use crate::products::Product;
+use sqlx::FromRow;
+use std::fmt;
+
+/// Why a price check could not read a price.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The page could not be downloaded or answered with an error status.
+    Http,
+    /// The page has no price where the scraper looks for one.
+    SelectorMiss,
+    /// A price was found but could not be read.
+    ParseError,
+}
+
+impl FailureKind {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "http" => Some(Self::Http),
+            "selector_miss" => Some(Self::SelectorMiss),
+            "parse_error" => Some(Self::ParseError),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Http => "http",
+            Self::SelectorMiss => "selector_miss",
+            Self::ParseError => "parse_error",
+        }
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Http => "HTTP error",
+            Self::SelectorMiss => "selector miss",
+            Self::ParseError => "parse error",
+        })
+    }
+}
+
+/// How one price check went, as far as the scraper is concerned.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CheckOutcome {
+    /// A price was read, or the page says the product is out of stock.
+    Success,
+    /// The page is the last one that gave a price or said the product is out of stock, as
+    /// only those are cached.
+    Unchanged,
+    Failure {
+        kind: FailureKind,
+        error: String,
+        /// The page that failed, when one was received.
+        html: Option<String>,
+    },
+}
+
+impl CheckOutcome {
+    /// Classifies the result of a price check, `html` being the page it read.
+    pub fn classify(
+        result: &Result<Option<PriceSnapshot>, PriceError>,
+        html: Option<&str>,
+    ) -> Self {
+        let (kind, error) = match result {
+            Ok(Some(_)) | Err(PriceError::Unavailable) => return Self::Success,
+            Err(PriceError::Unchanged) => return Self::Unchanged,
+            Ok(None) => (
+                FailureKind::SelectorMiss,
+                "no price found on the page".to_string(),
+            ),
+            Err(err @ (PriceError::RequestFailed(_) | PriceError::ReqwestError(_))) => {
+                (FailureKind::Http, err.to_string())
+            }
+            Err(err @ (PriceError::HtmlParseError | PriceError::PriceParseError(_))) => {
+                (FailureKind::ParseError, err.to_string())
+            }
+        };
+        Self::Failure {
+            kind,
+            error,
+            html: html.map(str::to_string),
+        }
+    }
+}
+
+/// Change worth telling the admins about after a check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthChange {
+    /// The failures just reached the threshold.
+    Broken,
+    /// A price was read again after the admins were told about the failures.
+    Recovered,
+}
+
+/// Consecutive failed checks of one product, stored in `scraper_health`.
+#[derive(Clone, Debug, Default, FromRow, PartialEq)]
+pub struct ScraperHealth {
+    pub consecutive_failures: i64,
+    pub failure_kind: Option<String>,
+    pub last_error: Option<String>,
+    /// Last page that failed to give a price, kept through failures that received no page.
+    pub failing_html: Option<String>,
+}
+
+impl ScraperHealth {
+    pub fn failure_kind(&self) -> Option<FailureKind> {
+        self.failure_kind.as_deref().and_then(FailureKind::parse)
+    }
+
+    /// Counts `outcome`, returning the change once the failures reach `threshold` or stop
+    /// after reaching it. Unchanged pages are the last good one, so they count as successes.
+    pub fn record(&mut self, outcome: CheckOutcome, threshold: i64) -> Option<HealthChange> {
+        match outcome {
+            CheckOutcome::Success | CheckOutcome::Unchanged => {
+                let was_broken = self.consecutive_failures >= threshold;
+                *self = Self::default();
+                return was_broken.then_some(HealthChange::Recovered);
+            }
+            CheckOutcome::Failure { kind, error, html } => {
+                self.consecutive_failures += 1;
+                self.failure_kind = Some(kind.as_str().to_string());
+                self.last_error = Some(error);
+                if html.is_some() {
+                    self.failing_html = html;
+                }
+            }
+        }
+        (self.consecutive_failures == threshold).then_some(HealthChange::Broken)
+    }
+}
+
+/// Alert sent to the admins when `product` stops giving prices.
+pub fn broken_message(product: &Product, health: &ScraperHealth) -> String {
+    let kind = health
+        .failure_kind()
+        .map_or("unknown failure".to_string(), |kind| kind.to_string());
+    format!(
+        "⚠️ The last {} checks of {} failed ({}): {}\n{}",
+        health.consecutive_failures,
+        product.name,
+        kind,
+        health.last_error.as_deref().unwrap_or("unknown error"),
+        product.url
+    )
+}
+
+/// Counts the outcome of a check of `product` and alerts the admins when its scraper breaks,
+/// with the failing page attached, or when it works again.
+pub async fn record_check(product: &Product, outcome: CheckOutcome) {
+    let mut health = get_health(product.id).await;
+    let change = health.record(outcome, *constants::SCRAPER_FAILURE_THRESHOLD);
+    set_health(product.id, &health).await;
+
+    match change {
+        Some(HealthChange::Broken) => {
+            let message = broken_message(product, &health);
+            log::warn!("{}", message);
+            match &health.failing_html {
+                Some(html) => {
+                    let file_name = format!("{}.html", product.name);
+                    chat::notify_admins_with_file(&message, &file_name, html.as_bytes()).await;
+                }
+                None => chat::notify_admins(&message).await,
+            }
+        }
+        Some(HealthChange::Recovered) => {
+            let message = format!("✅ Prices of {} are read again.", product.name);
+            log::info!("{}", message);
+            chat::notify_admins(&message).await;
+        }
+        None => {}
+    }
+}
+
+pub async fn get_health(product_id: i64) -> ScraperHealth {
+    let db = db::get_db().await;
+    let health_query = sqlx::query_as::<_, ScraperHealth>(
+        "SELECT consecutive_failures, failure_kind, last_error, failing_html
+         FROM scraper_health WHERE product_id = ?",
+    )
+    .bind(product_id)
+    .fetch_optional(&db)
+    .await;
+
+    match health_query {
+        Ok(health) => health.unwrap_or_default(),
+        Err(err) => {
+            log::error!(
+                "Failed to query scraper health of product {}! {:?}",
+                product_id,
+                err
+            );
+            ScraperHealth::default()
+        }
+    }
+}
+
+async fn set_health(product_id: i64, health: &ScraperHealth) {
+    let db = db::get_db().await;
+    let upsert_result = sqlx::query(
+        "INSERT INTO scraper_health
+         (product_id, consecutive_failures, failure_kind, last_error, failing_html)
+         VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT (product_id) DO UPDATE
+         SET consecutive_failures = excluded.consecutive_failures,
+             failure_kind = excluded.failure_kind, last_error = excluded.last_error,
+             failing_html = excluded.failing_html, update_time = CURRENT_TIMESTAMP",
+    )
+    .bind(product_id)
+    .bind(health.consecutive_failures)
+    .bind(&health.failure_kind)
+    .bind(&health.last_error)
+    .bind(&health.failing_html)
+    .execute(&db)
+    .await;
+
+    if let Err(err) = upsert_result {
+        log::error!(
+            "Failed to store scraper health of product {}! {:?}",
+            product_id,
+            err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    fn failure(kind: FailureKind, html: Option<&str>) -> CheckOutcome {
+        CheckOutcome::Failure {
+            kind,
+            error: kind.to_string(),
+            html: html.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let classify = |result| CheckOutcome::classify(&result, Some("<html></html>"));
+        assert_eq!(
+            classify(Err(PriceError::Unavailable)),
+            CheckOutcome::Success
+        );
+        assert_eq!(
+            classify(Err(PriceError::Unchanged)),
+            CheckOutcome::Unchanged
+        );
+        assert_eq!(
+            classify(Ok(None)),
+            CheckOutcome::Failure {
+                kind: FailureKind::SelectorMiss,
+                error: "no price found on the page".to_string(),
+                html: Some("<html></html>".to_string()),
+            }
+        );
+        assert!(matches!(
+            classify(Err(PriceError::RequestFailed(StatusCode::FORBIDDEN))),
+            CheckOutcome::Failure {
+                kind: FailureKind::Http,
+                ..
+            }
+        ));
+        assert!(matches!(
+            classify(Err(PriceError::PriceParseError("€abc".to_string()))),
+            CheckOutcome::Failure {
+                kind: FailureKind::ParseError,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_record_threshold() {
+        let mut health = ScraperHealth::default();
+        let miss = || failure(FailureKind::SelectorMiss, Some("<p>new layout</p>"));
+        assert_eq!(health.record(CheckOutcome::Unchanged, 3), None);
+        assert_eq!(health.consecutive_failures, 0);
+
+        // A transient error followed by the last good page doesn't add up.
+        assert_eq!(health.record(failure(FailureKind::Http, None), 3), None);
+        assert_eq!(health.record(CheckOutcome::Unchanged, 3), None);
+        assert_eq!(health, ScraperHealth::default());
+
+        assert_eq!(health.record(miss(), 3), None);
+        assert_eq!(health.record(miss(), 3), None);
+        assert_eq!(
+            health.record(failure(FailureKind::Http, None), 3),
+            Some(HealthChange::Broken)
+        );
+        assert_eq!(health.failure_kind(), Some(FailureKind::Http));
+        assert_eq!(health.failing_html.as_deref(), Some("<p>new layout</p>"));
+        // Alerted once, until the scraper recovers.
+        assert_eq!(
+            health.record(failure(FailureKind::SelectorMiss, Some("<p>newer</p>")), 3),
+            None
+        );
+        assert_eq!(health.consecutive_failures, 4);
+        assert_eq!(health.failing_html.as_deref(), Some("<p>newer</p>"));
+
+        assert_eq!(
+            health.record(CheckOutcome::Success, 3),
+            Some(HealthChange::Recovered)
+        );
+        assert_eq!(health, ScraperHealth::default());
+        assert_eq!(health.record(CheckOutcome::Success, 3), None);
+
+        for _ in 0..3 {
+            health.record(miss(), 3);
+        }
+        assert_eq!(
+            health.record(CheckOutcome::Unchanged, 3),
+            Some(HealthChange::Recovered)
+        );
+        assert_eq!(health, ScraperHealth::default());
+    }
+}
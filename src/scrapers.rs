@@ -0,0 +1,519 @@
+use crate::db;
+use crate::milk_price::PriceError;
+use crate::money::{Currency, Money};
+use crate::promotions::Promotion;
+use crate::retailers::PriceSource;
+use crate::stock::Availability;
+use crate::structured_data;
+use crate::units::{PackSize, UnitPrice};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use sqlx::FromRow;
+use std::sync::{Arc, RwLock};
+
+/// Definitions loaded from the `scrapers` table, see [`reload`].
+static SCRAPERS: Lazy<RwLock<Vec<Arc<ScraperDefinition>>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+#[derive(Clone, FromRow, Debug)]
+struct ScraperSchema {
+    name: String,
+    url_pattern: String,
+    price_path: String,
+    price_regex: Option<String>,
+    decimal_separator: String,
+    currency: String,
+    unit_price_path: Option<String>,
+    pack_size_path: Option<String>,
+    regular_price_path: Option<String>,
+    promo_label_path: Option<String>,
+    out_of_stock_path: Option<String>,
+}
+
+/// Where a value is read from: a CSS selector, optionally followed by `@attribute` to read an
+/// attribute of the first matching element instead of its text, e.g.
+/// `meta[itemprop=price]@content`.
+#[derive(Debug)]
+struct Path {
+    selector: Selector,
+    attribute: Option<String>,
+    spec: String,
+}
+
+impl Path {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        let (selector, attribute) = match spec.rsplit_once('@') {
+            Some((selector, attribute))
+                if !attribute.is_empty()
+                    && attribute
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') =>
+            {
+                (selector, Some(attribute.to_string()))
+            }
+            _ => (spec, None),
+        };
+        let selector =
+            Selector::parse(selector).map_err(|_| format!("invalid selector '{}'", spec))?;
+        Ok(Self {
+            selector,
+            attribute,
+            spec: spec.to_string(),
+        })
+    }
+
+    fn select(&self, document: &Html) -> Option<String> {
+        let element = document.select(&self.selector).next()?;
+        let text = match &self.attribute {
+            Some(attribute) => element.value().attr(attribute)?.to_string(),
+            None => element.text().collect::<String>(),
+        };
+        Some(text.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+/// A retailer described as data instead of a [`PriceSource`] written in Rust: which pages it
+/// reads and where each value is on them.
+#[derive(Debug)]
+pub struct ScraperDefinition {
+    name: String,
+    /// Matched against the page URL.
+    url_pattern: Regex,
+    price: Path,
+    /// Cleans up the price text before parsing: the first capture group, or the whole match
+    /// without groups, is kept.
+    price_regex: Option<Regex>,
+    decimal_separator: char,
+    currency: Currency,
+    unit_price: Option<Path>,
+    pack_size: Option<Path>,
+    regular_price: Option<Path>,
+    promo_label: Option<Path>,
+    /// Any element matching it means the product is out of stock.
+    out_of_stock: Option<Path>,
+}
+
+impl TryFrom<ScraperSchema> for ScraperDefinition {
+    type Error = String;
+
+    fn try_from(schema: ScraperSchema) -> Result<Self, Self::Error> {
+        let regex = |pattern: &str| {
+            Regex::new(pattern).map_err(|err| format!("invalid regex '{}': {}", pattern, err))
+        };
+        let optional_path = |spec: Option<String>| spec.as_deref().map(Path::parse).transpose();
+        let decimal_separator = match schema.decimal_separator.as_str() {
+            "," => ',',
+            "." => '.',
+            other => return Err(format!("invalid decimal separator '{}'", other)),
+        };
+        Ok(Self {
+            url_pattern: regex(&schema.url_pattern)?,
+            price: Path::parse(&schema.price_path)?,
+            price_regex: schema.price_regex.as_deref().map(regex).transpose()?,
+            decimal_separator,
+            currency: Currency::parse(&schema.currency)
+                .ok_or_else(|| format!("invalid currency '{}'", schema.currency))?,
+            unit_price: optional_path(schema.unit_price_path)?,
+            pack_size: optional_path(schema.pack_size_path)?,
+            regular_price: optional_path(schema.regular_price_path)?,
+            promo_label: optional_path(schema.promo_label_path)?,
+            out_of_stock: optional_path(schema.out_of_stock_path)?,
+            name: schema.name,
+        })
+    }
+}
+
+impl ScraperDefinition {
+    pub fn matches_url(&self, url: &str) -> bool {
+        self.url_pattern.is_match(url)
+    }
+
+    /// Parses an amount with this retailer's currency and decimal separator, after the price
+    /// regex cleanup when `clean_up` is set.
+    fn parse_amount(&self, text: &str, clean_up: bool) -> Option<Money> {
+        let text = match self.price_regex.as_ref().filter(|_| clean_up) {
+            Some(regex) => {
+                let captures = regex.captures(text)?;
+                captures.get(1).or_else(|| captures.get(0))?.as_str()
+            }
+            None => text,
+        };
+        let amount: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        Money::parse_with_decimal(
+            amount.trim_matches(|c: char| !c.is_ascii_digit() && c != '-'),
+            self.decimal_separator,
+            self.currency,
+        )
+    }
+
+    fn extract_regular_price(&self, document: &Html) -> Option<Money> {
+        let text = self.regular_price.as_ref()?.select(document)?;
+        self.parse_amount(&text, true)
+    }
+
+    /// What each rule of the definition read from `document`, for `/testscraper`.
+    pub fn explain(&self, document: &Html) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Scraper '{}' ({}, decimal separator '{}')",
+            self.name, self.currency, self.decimal_separator
+        )];
+        let rules = [
+            ("price", Some(&self.price)),
+            ("unit price", self.unit_price.as_ref()),
+            ("pack size", self.pack_size.as_ref()),
+            ("regular price", self.regular_price.as_ref()),
+            ("promotion label", self.promo_label.as_ref()),
+            ("out of stock", self.out_of_stock.as_ref()),
+        ];
+        for (label, path) in rules {
+            let Some(path) = path else {
+                continue;
+            };
+            let line = match path.select(document) {
+                Some(text) => {
+                    let value = match label {
+                        "price" => self
+                            .parse_amount(&text, true)
+                            .map(|price| price.to_string()),
+                        "unit price" => self.extract_unit_price(document).map(|p| p.to_string()),
+                        "pack size" => PackSize::parse(&text).map(|size| size.to_string()),
+                        "regular price" => {
+                            self.extract_regular_price(document).map(|p| p.to_string())
+                        }
+                        _ => Some(text.clone()),
+                    };
+                    format!(
+                        "{} `{}`: '{}' → {}",
+                        label,
+                        path.spec,
+                        text,
+                        value.unwrap_or_else(|| "unreadable".to_string())
+                    )
+                }
+                None => format!("{} `{}`: nothing matched", label, path.spec),
+            };
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+impl PriceSource for ScraperDefinition {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        self.matches_url(url.as_str())
+    }
+
+    fn extract_price(&self, document: &Html) -> Result<Option<Money>, PriceError> {
+        let Some(text) = self.price.select(document) else {
+            return Ok(None);
+        };
+        self.parse_amount(&text, true)
+            .map(Some)
+            .ok_or(PriceError::PriceParseError(text))
+    }
+
+    /// The unit price reads like `0,99 €/L`: amount, then the unit after the last `/`.
+    fn extract_unit_price(&self, document: &Html) -> Option<UnitPrice> {
+        let text = self.unit_price.as_ref()?.select(document)?;
+        let (price, unit) = text.rsplit_once('/')?;
+        UnitPrice::parse_per(self.parse_amount(price, false)?, unit)
+    }
+
+    fn extract_pack_size(&self, document: &Html) -> Option<PackSize> {
+        PackSize::parse(&self.pack_size.as_ref()?.select(document)?)
+    }
+
+    fn extract_promotion(&self, document: &Html) -> Option<Promotion> {
+        let label = self
+            .promo_label
+            .as_ref()
+            .and_then(|path| path.select(document));
+        Promotion::new(self.extract_regular_price(document), label, None)
+    }
+
+    fn extract_availability(&self, document: &Html) -> Option<Availability> {
+        self.out_of_stock
+            .as_ref()?
+            .select(document)
+            .map(|_| Availability::OutOfStock)
+    }
+
+    /// Definitions are written for a shop on purpose, so they can fix a wrong JSON-LD price.
+    fn overrides_structured_data(&self) -> bool {
+        true
+    }
+}
+
+/// Replaces the loaded definitions with the valid ones of the `scrapers` table, returning the
+/// problems of the invalid ones.
+pub async fn reload() -> Vec<String> {
+    let db = db::get_db().await;
+    let scrapers_query = sqlx::query_as::<_, ScraperSchema>("SELECT * FROM scrapers ORDER BY id")
+        .fetch_all(&db)
+        .await;
+    let schemas = match scrapers_query {
+        Ok(schemas) => schemas,
+        Err(err) => {
+            log::error!("Failed to query scrapers! {:?}", err);
+            return vec![format!("Failed to query scrapers: {}", err)];
+        }
+    };
+
+    let mut definitions = vec![];
+    let mut problems = vec![];
+    for schema in schemas {
+        let name = schema.name.clone();
+        match ScraperDefinition::try_from(schema) {
+            Ok(definition) => definitions.push(Arc::new(definition)),
+            Err(err) => {
+                log::error!("Skipping scraper '{}': {}", name, err);
+                problems.push(format!("{}: {}", name, err));
+            }
+        }
+    }
+    log::info!("Loaded {} scrapers", definitions.len());
+    *SCRAPERS.write().unwrap() = definitions;
+    problems
+}
+
+/// Names of the loaded definitions.
+pub fn loaded() -> Vec<String> {
+    SCRAPERS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|definition| definition.name.clone())
+        .collect()
+}
+
+/// The first loaded definition whose pattern matches `url`. Definitions take precedence over
+/// the built-in adapters, so a retailer's adapter can be fixed without a release.
+pub fn find_scraper(url: &str) -> Option<Arc<ScraperDefinition>> {
+    SCRAPERS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|definition| definition.matches_url(url))
+        .cloned()
+}
+
+/// Describes what the structured data and the scraper picked for `url` extract from
+/// `document`, for `/testscraper`.
+pub fn describe_extraction(
+    url: &str,
+    document: &Html,
+    scraper: Option<&ScraperDefinition>,
+    builtin: Option<&dyn PriceSource>,
+) -> Vec<String> {
+    let or_none = |value: Option<String>| value.unwrap_or_else(|| "none".to_string());
+    let mut lines = vec![format!("Testing {}", url)];
+    lines.push(format!(
+        "structured data price: {}",
+        or_none(structured_data::extract_price(document).map(|price| price.to_string()))
+    ));
+    lines.push(format!(
+        "structured data availability: {}",
+        or_none(structured_data::extract_availability(document).map(|a| a.to_string()))
+    ));
+
+    if let Some(scraper) = scraper {
+        lines.extend(scraper.explain(document));
+        return lines;
+    }
+    let Some(source) = builtin else {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        lines.push(format!(
+            "No scraper matches '{}', every built-in adapter is tried.",
+            host
+        ));
+        return lines;
+    };
+    lines.push(format!("Built-in adapter '{}'", source.name()));
+    lines.push(format!(
+        "price: {}",
+        match source.extract_price(document) {
+            Ok(price) => or_none(price.map(|price| price.to_string())),
+            Err(err) => err.to_string(),
+        }
+    ));
+    lines.push(format!(
+        "unit price: {}",
+        or_none(source.extract_unit_price(document).map(|p| p.to_string()))
+    ));
+    lines.push(format!(
+        "pack size: {}",
+        or_none(source.extract_pack_size(document).map(|p| p.to_string()))
+    ));
+    lines.push(format!(
+        "promotion: {}",
+        or_none(source.extract_promotion(document).map(|p| p.to_string()))
+    ));
+    lines.push(format!(
+        "out of stock: {}",
+        source.extract_availability(document) == Some(Availability::OutOfStock)
+    ));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::milk_price::{extract_price, extract_snapshot};
+
+    const LIDL_FIXTURE: &str = r#"
+        <div class="m-price">
+            <span class="m-price__label">Preço normal 1.649,00</span>
+            <span class="m-price__price" data-price="1.299,99">Agora 1.299,99 €</span>
+            <span class="m-price__base">0,65 €/l</span>
+            <span class="m-price__badge">-21%</span>
+        </div>
+        <div class="pack">Embalagem 2 l</div>"#;
+
+    fn lidl() -> ScraperDefinition {
+        ScraperDefinition::try_from(ScraperSchema {
+            name: "lidl".to_string(),
+            url_pattern: r"^https?://(www\.)?lidl\.pt/".to_string(),
+            price_path: ".m-price__price".to_string(),
+            price_regex: Some(r"([\d.]+,\d{2})".to_string()),
+            decimal_separator: ",".to_string(),
+            currency: "EUR".to_string(),
+            unit_price_path: Some(".m-price__base".to_string()),
+            pack_size_path: Some(".pack".to_string()),
+            regular_price_path: Some(".m-price__label".to_string()),
+            promo_label_path: Some(".m-price__badge".to_string()),
+            out_of_stock_path: Some(".sold-out".to_string()),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_definition_extraction() {
+        let scraper = lidl();
+        assert!(scraper.matches_url("https://www.lidl.pt/p/leite/p100"));
+        assert!(!scraper.matches_url("https://www.continente.pt/produto/leite.html"));
+
+        let document = Html::parse_document(LIDL_FIXTURE);
+        let snapshot = extract_snapshot(&document, Some(&scraper))
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.price, Money::eur(129999));
+        assert_eq!(snapshot.unit_price.unwrap().to_string(), "0,65 €/L");
+        assert_eq!(snapshot.pack_size.unwrap().to_string(), "2 L");
+        let promotion = snapshot.promotion.unwrap();
+        assert_eq!(promotion.regular_price, Some(Money::eur(164900)));
+        assert_eq!(promotion.label.as_deref(), Some("-21%"));
+        assert_eq!(snapshot.availability, Availability::InStock);
+    }
+
+    #[test]
+    fn test_definition_overrides_structured_data() {
+        let scraper = lidl();
+        let url = Url::parse("https://www.lidl.pt/p/leite/p100").unwrap();
+        assert!(PriceSource::matches(&scraper, &url));
+        let url = Url::parse("https://www.continente.pt/produto/leite.html").unwrap();
+        assert!(!PriceSource::matches(&scraper, &url));
+
+        let document = Html::parse_document(&format!(
+            r#"<script type="application/ld+json">
+            {{"@type": "Product", "offers": {{"price": "12.99", "availability": "InStock"}}}}
+            </script>{}<div class="sold-out">Esgotado</div>"#,
+            LIDL_FIXTURE
+        ));
+        assert_eq!(
+            extract_price(&document, Some(&scraper)).unwrap(),
+            Some(Money::eur(129999))
+        );
+        let snapshot = extract_snapshot(&document, Some(&scraper))
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.availability, Availability::OutOfStock);
+
+        // Pages the definition has no price on still get the structured data one.
+        let document = Html::parse_document(
+            r#"<script type="application/ld+json">
+            {"@type": "Product", "offers": {"price": "12.99"}}</script>"#,
+        );
+        assert_eq!(
+            extract_price(&document, Some(&scraper)).unwrap(),
+            Some(Money::eur(1299))
+        );
+    }
+
+    #[test]
+    fn test_attribute_path_and_currency() {
+        let scraper = ScraperDefinition::try_from(ScraperSchema {
+            name: "shop".to_string(),
+            url_pattern: r"shop\.example".to_string(),
+            price_path: ".m-price__price@data-price".to_string(),
+            price_regex: None,
+            decimal_separator: ".".to_string(),
+            currency: "USD".to_string(),
+            unit_price_path: None,
+            pack_size_path: None,
+            regular_price_path: None,
+            promo_label_path: None,
+            out_of_stock_path: None,
+        })
+        .unwrap();
+        let document = Html::parse_document(
+            r#"<span class="m-price__price" data-price="1,299.99">$1,299.99</span>"#,
+        );
+        assert_eq!(
+            scraper.extract_price(&document).unwrap(),
+            Some(Money::new(129999, Currency::parse("USD").unwrap()))
+        );
+    }
+
+    #[test]
+    fn test_invalid_definition() {
+        let mut schema = ScraperSchema {
+            name: "broken".to_string(),
+            url_pattern: "(".to_string(),
+            price_path: ".price".to_string(),
+            price_regex: None,
+            decimal_separator: ",".to_string(),
+            currency: "EUR".to_string(),
+            unit_price_path: None,
+            pack_size_path: None,
+            regular_price_path: None,
+            promo_label_path: None,
+            out_of_stock_path: None,
+        };
+        assert!(ScraperDefinition::try_from(schema.clone()).is_err());
+        schema.url_pattern = "shop".to_string();
+        schema.decimal_separator = ";".to_string();
+        assert!(ScraperDefinition::try_from(schema.clone()).is_err());
+        schema.decimal_separator = ",".to_string();
+        schema.price_path = "<<".to_string();
+        assert!(ScraperDefinition::try_from(schema).is_err());
+    }
+
+    #[test]
+    fn test_explain() {
+        let document = Html::parse_document(LIDL_FIXTURE);
+        let lines = describe_extraction(
+            "https://www.lidl.pt/p/leite/p100",
+            &document,
+            Some(&lidl()),
+            None,
+        );
+        assert_eq!(lines[1], "structured data price: none");
+        assert_eq!(
+            lines[4],
+            "price `.m-price__price`: 'Agora 1.299,99 €' → 1299,99 €"
+        );
+        assert_eq!(
+            lines[lines.len() - 1],
+            "out of stock `.sold-out`: nothing matched"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use crate::db;
+use crate::scheduler::DEFAULT_TIMEZONE;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{FromRow, SqlitePool};
+
+/// Format of the timestamps stored in `service_runs`, the same as SQLite's `CURRENT_TIMESTAMP`.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// Runs older than this are deleted when a service records a new one.
+const RETENTION_DAYS: i64 = 90;
+/// Newest runs kept per service, price checkers run several times an hour.
+const MAX_RUNS_PER_SERVICE: i64 = 1000;
+
+/// Aggregated history of the runs of one service.
+#[derive(Clone, FromRow, Debug, Default)]
+pub struct ServiceRunStats {
+    /// Runs still kept, see [`RETENTION_DAYS`] and [`MAX_RUNS_PER_SERVICE`].
+    pub run_count: i64,
+    pub failure_count: i64,
+    pub last_run: Option<String>,
+    pub last_run_success: Option<bool>,
+    pub last_success: Option<String>,
+    pub last_error: Option<String>,
+    pub last_error_time: Option<String>,
+}
+
+/// Stores a run of `service_id`, then drops its runs past the retention limits.
+pub async fn record_run(service_id: i64, started_at: DateTime<Utc>, result: &Result<(), String>) {
+    let db = db::get_db().await;
+    insert_run(&db, service_id, started_at, Utc::now(), result).await;
+    prune_runs(&db, service_id, RETENTION_DAYS, MAX_RUNS_PER_SERVICE).await;
+}
+
+async fn insert_run(
+    db: &SqlitePool,
+    service_id: i64,
+    started_at: DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+    result: &Result<(), String>,
+) {
+    let insert_result = sqlx::query(
+        "INSERT INTO service_runs (service_id, started_at, finished_at, success, error)
+         VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(service_id)
+    .bind(started_at.format(TIMESTAMP_FORMAT).to_string())
+    .bind(finished_at.format(TIMESTAMP_FORMAT).to_string())
+    .bind(result.is_ok())
+    .bind(result.as_ref().err())
+    .execute(db)
+    .await;
+
+    if let Err(err) = insert_result {
+        log::error!("Insert of run of service {} failed! {:?}", service_id, err);
+    }
+}
+
+async fn prune_runs(db: &SqlitePool, service_id: i64, retention_days: i64, max_runs: i64) {
+    let prune_result = sqlx::query(
+        "DELETE FROM service_runs WHERE service_id = ?1
+         AND (started_at < datetime('now', ?2) OR id NOT IN (
+             SELECT id FROM service_runs WHERE service_id = ?1 ORDER BY id DESC LIMIT ?3))",
+    )
+    .bind(service_id)
+    .bind(format!("-{} days", retention_days))
+    .bind(max_runs)
+    .execute(db)
+    .await;
+
+    if let Err(err) = prune_result {
+        log::error!("Failed to prune runs of service {}! {:?}", service_id, err);
+    }
+}
+
+pub async fn get_stats(service_id: i64) -> ServiceRunStats {
+    let db = db::get_db().await;
+    query_stats(&db, service_id).await
+}
+
+async fn query_stats(db: &SqlitePool, service_id: i64) -> ServiceRunStats {
+    let stats_query = sqlx::query_as::<_, ServiceRunStats>(
+        "SELECT
+            COUNT(*) AS run_count,
+            COUNT(*) FILTER (WHERE NOT success) AS failure_count,
+            MAX(started_at) AS last_run,
+            (SELECT success FROM service_runs WHERE service_id = ?1
+                ORDER BY started_at DESC, id DESC LIMIT 1) AS last_run_success,
+            MAX(CASE WHEN success THEN started_at END) AS last_success,
+            (SELECT error FROM service_runs WHERE service_id = ?1 AND NOT success
+                ORDER BY started_at DESC, id DESC LIMIT 1) AS last_error,
+            MAX(CASE WHEN NOT success THEN started_at END) AS last_error_time
+         FROM service_runs WHERE service_id = ?1",
+    )
+    .bind(service_id)
+    .fetch_one(db)
+    .await;
+
+    match stats_query {
+        Ok(stats) => stats,
+        Err(err) => {
+            log::error!("Failed to query runs of service {}! {:?}", service_id, err);
+            ServiceRunStats::default()
+        }
+    }
+}
+
+/// Formats a UTC timestamp in the local timezone, e.g. `2024-06-01 15:00`.
+pub fn format_local_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&DEFAULT_TIMEZONE)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// Formats a timestamp read from the database in the local timezone.
+pub fn format_stored_time(time: &str) -> String {
+    match NaiveDateTime::parse_from_str(time, TIMESTAMP_FORMAT) {
+        Ok(time) => format_local_time(time.and_utc()),
+        Err(_) => time.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    /// In-memory database with services 1 to 3, on one connection as each opens its own.
+    async fn test_db() -> SqlitePool {
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query(include_str!("../db/creation.sql"))
+            .execute(&db)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO services (name) VALUES ('a'), ('b'), ('c')")
+            .execute(&db)
+            .await
+            .unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn test_get_stats() {
+        let db = test_db().await;
+        let start = Utc::now() - Duration::hours(3);
+        let results = [Ok(()), Err("timeout".to_string()), Ok(())];
+        for (hour, result) in results.iter().enumerate() {
+            let started_at = start + Duration::hours(hour as i64);
+            insert_run(&db, 1, started_at, started_at, result).await;
+        }
+        insert_run(&db, 2, start, start, &Err("other service".to_string())).await;
+
+        let stats = query_stats(&db, 1).await;
+        assert_eq!(stats.run_count, 3);
+        assert_eq!(stats.failure_count, 1);
+        assert_eq!(stats.last_run_success, Some(true));
+        assert_eq!(stats.last_error.as_deref(), Some("timeout"));
+        let second_run = (start + Duration::hours(1))
+            .format(TIMESTAMP_FORMAT)
+            .to_string();
+        assert_eq!(stats.last_error_time, Some(second_run));
+        let third_run = (start + Duration::hours(2))
+            .format(TIMESTAMP_FORMAT)
+            .to_string();
+        assert_eq!(stats.last_success, Some(third_run.clone()));
+        assert_eq!(stats.last_run, Some(third_run));
+
+        let stats = query_stats(&db, 3).await;
+        assert_eq!((stats.run_count, stats.failure_count), (0, 0));
+        assert_eq!(stats.last_run, None);
+    }
+
+    #[tokio::test]
+    async fn test_prune_runs() {
+        let db = test_db().await;
+        let now = Utc::now();
+        insert_run(
+            &db,
+            1,
+            now - Duration::days(40),
+            now,
+            &Err("old".to_string()),
+        )
+        .await;
+        for minutes in (0..4).rev() {
+            let started_at = now - Duration::minutes(minutes);
+            insert_run(&db, 1, started_at, started_at, &Ok(())).await;
+        }
+        insert_run(&db, 2, now - Duration::days(40), now, &Ok(())).await;
+
+        prune_runs(&db, 1, 30, 3).await;
+        let stats = query_stats(&db, 1).await;
+        assert_eq!((stats.run_count, stats.failure_count), (3, 0));
+        assert_eq!(query_stats(&db, 2).await.run_count, 1);
+    }
+}
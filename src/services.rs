@@ -1,11 +1,15 @@
 use crate::db;
-use core::future::Future;
+use crate::scheduler::{self, Schedule};
+use crate::service_runs;
+use crate::supervisor::{self, ServiceHealth, ServiceState};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
 use sqlx::{FromRow, Sqlite};
-use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as StdMutex};
 use tokio;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
+use tokio::time::sleep;
 
 #[derive(Clone, FromRow, Debug)]
 struct ServiceSchema {
@@ -15,12 +19,84 @@ struct ServiceSchema {
     creation_time: String,
 }
 
+/// Runtime information handed to a service when it starts.
+#[derive(Clone, Debug)]
+pub struct ServiceContext {
+    pub id: i64,
+    pub name: String,
+    /// Schedule declared by the service, used when none is stored for it.
+    pub default_schedule: Option<Schedule>,
+    /// When the service runs next, shared with [`Service`] for `/status`.
+    pub next_run: Arc<StdMutex<Option<DateTime<Utc>>>>,
+}
+
+impl ServiceContext {
+    /// Schedule stored with `/schedule`, else the declared one, else every 4 hours.
+    pub async fn schedule(&self) -> Schedule {
+        match scheduler::get_schedule(self.id).await {
+            Some(schedule) => schedule,
+            None => self
+                .default_schedule
+                .clone()
+                .unwrap_or_else(|| Schedule::every(chrono::Duration::hours(4))),
+        }
+    }
+
+    /// Sleeps until the next run of the service's schedule.
+    pub async fn wait_for_next_run(&self) {
+        let schedule = self.schedule().await;
+        let now = Utc::now();
+        let next_run = schedule.next_run(now, rand::random::<f64>());
+        log::info!(
+            "Service '{}' ({}) next runs at {}",
+            &self.name,
+            schedule,
+            next_run
+        );
+        *self.next_run.lock().unwrap() = Some(next_run);
+        sleep((next_run - now).to_std().unwrap_or_default()).await;
+        *self.next_run.lock().unwrap() = None;
+    }
+
+    /// Stores the outcome of one run started at `started_at` in the `service_runs` table.
+    pub async fn record_run(&self, started_at: DateTime<Utc>, result: Result<(), String>) {
+        service_runs::record_run(self.id, started_at, &result).await;
+    }
+}
+
+/// A long running job managed by [`Services`] that can be toggled and inspected from chat.
+pub trait BotService: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn description(&self) -> String;
+
+    /// Runs the service until it is aborted, called again every time the service is enabled.
+    fn run(self: Arc<Self>, ctx: ServiceContext) -> BoxFuture<'static, ()>;
+
+    /// Handles `/service <name> <args>`, returning the reply or `None` for unknown arguments.
+    fn on_command<'a>(&'a self, _args: &'a str) -> BoxFuture<'a, Option<String>> {
+        Box::pin(async { None })
+    }
+
+    /// Schedule used until another one is set with `/schedule`.
+    fn schedule(&self) -> Option<Schedule> {
+        None
+    }
+
+    /// Current state of the service as label and value pairs.
+    fn status(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+}
+
 pub struct Service {
     pub id: i64,
     pub name: String,
     pub enable: bool,
     pub creation_time: String,
-    future_factory: Box<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>,
+    pub service: Arc<dyn BotService>,
+    pub health: Arc<StdMutex<ServiceHealth>>,
+    next_run: Arc<StdMutex<Option<DateTime<Utc>>>>,
     join_handle: Option<JoinHandle<()>>,
 }
 
@@ -30,21 +106,35 @@ impl Service {
         name: String,
         enable: bool,
         creation_time: String,
-        future_factory: Box<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>,
+        service: Arc<dyn BotService>,
     ) -> Self {
         Self {
             id,
             name,
             enable,
             creation_time,
-            future_factory,
+            service,
+            health: Arc::new(StdMutex::new(ServiceHealth::default())),
+            next_run: Arc::new(StdMutex::new(None)),
             join_handle: None,
         }
     }
 
+    pub fn context(&self) -> ServiceContext {
+        ServiceContext {
+            id: self.id,
+            name: self.name.clone(),
+            default_schedule: self.service.schedule(),
+            next_run: self.next_run.clone(),
+        }
+    }
+
+    /// Starts the service under a supervisor that restarts it when it crashes.
     pub fn begin(&mut self) -> &mut Self {
         if self.enable {
-            let future = (self.future_factory)();
+            *self.health.lock().unwrap() = ServiceHealth::default();
+            let future =
+                supervisor::supervise(self.service.clone(), self.context(), self.health.clone());
             log::info!("Beginning '{}' service..", &self.name);
             self.join_handle = Some(tokio::spawn(future));
         }
@@ -56,9 +146,19 @@ impl Service {
             log::info!("Ending '{}' service..", &self.name);
             join_handle.abort();
         }
+        self.health.lock().unwrap().state = ServiceState::Stopped;
+        *self.next_run.lock().unwrap() = None;
         self
     }
 
+    pub fn next_run(&self) -> Option<DateTime<Utc>> {
+        *self.next_run.lock().unwrap()
+    }
+
+    pub fn health(&self) -> ServiceHealth {
+        self.health.lock().unwrap().clone()
+    }
+
     pub async fn set_enable_state(&mut self, state: bool) {
         if state == self.enable {
             return;
@@ -71,7 +171,8 @@ impl Service {
         }
 
         let db = db::get_db().await;
-        let update_enable_state = sqlx::query("UPDATE services SET enable = False WHERE id = ?;")
+        let update_enable_state = sqlx::query("UPDATE services SET enable = ? WHERE id = ?;")
+            .bind(self.enable)
             .bind(self.id)
             .execute(&db)
             .await;
@@ -112,26 +213,22 @@ impl Services {
         }
     }
 
-    pub async fn create_service(
-        &mut self,
-        name: String,
-        enable: bool,
-        future_factory: Box<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>,
-    ) {
+    pub async fn create_service(&mut self, enable: bool, service: Arc<dyn BotService>) {
+        let name = service.name().to_string();
         let service_index = self.get_service_internally(&name).await;
 
-        if service_index.is_some() {
+        if let Some(service_index) = service_index {
             log::error!(
                 "Service with name '{}' already exists with index '{}'",
                 &name,
-                service_index.unwrap()
+                service_index
             );
             return;
         }
 
-        let service = self.get_service_from_db(&name).await;
+        let service_schema = self.get_service_from_db(&name).await;
         let new_service: Arc<Mutex<Service>>;
-        if let Some(service_schema) = service {
+        if let Some(service_schema) = service_schema {
             log::info!(
                 "Service with name '{}' already exists in database, recovering it..",
                 &name
@@ -142,7 +239,7 @@ impl Services {
                 service_schema.name,
                 service_schema.enable,
                 service_schema.creation_time,
-                future_factory,
+                service,
             )));
         } else {
             log::info!(
@@ -176,7 +273,7 @@ impl Services {
                 service_schema.name,
                 service_schema.enable,
                 service_schema.creation_time,
-                future_factory,
+                service,
             )));
         }
         {
@@ -186,6 +283,28 @@ impl Services {
         self.services.push(new_service);
     }
 
+    /// Stops the service with `name` and forgets it, both in memory and in the database.
+    pub async fn remove_service(&mut self, name: &str) -> bool {
+        let Some(service_index) = self.get_service_internally(name).await else {
+            return false;
+        };
+
+        let service_guard = self.services.remove(service_index);
+        let mut service = service_guard.lock().await;
+        service.end();
+
+        let db = db::get_db().await;
+        let delete_result = sqlx::query("DELETE FROM services WHERE id = ?;")
+            .bind(service.id)
+            .execute(&db)
+            .await;
+
+        if let Err(err) = delete_result {
+            log::error!("Delete of service with name: {} failed! {:?}", name, err);
+        }
+        true
+    }
+
     pub async fn get_service(&self, name: &str) -> Option<Arc<Mutex<Service>>> {
         for service_guard in self.services.iter() {
             let service = service_guard.lock().await;
@@ -0,0 +1,173 @@
+use crate::db;
+use crate::milk_price::PriceSnapshot;
+use std::fmt;
+
+/// Whether a product can be bought, as shown on its page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Availability {
+    InStock,
+    OutOfStock,
+}
+
+impl Availability {
+    /// Parses schema.org availability values, either as URLs (`https://schema.org/InStock`)
+    /// or bare names, and the OpenGraph `instock`/`oos` values.
+    pub fn parse(text: &str) -> Option<Self> {
+        let name = text
+            .trim()
+            .rsplit('/')
+            .next()?
+            .to_lowercase()
+            .replace(' ', "");
+        match name.as_str() {
+            "instock" | "limitedavailability" | "instoreonly" | "onlineonly" => Some(Self::InStock),
+            "outofstock" | "oos" | "soldout" | "discontinued" => Some(Self::OutOfStock),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::InStock => "in_stock",
+            Self::OutOfStock => "out_of_stock",
+        }
+    }
+
+    fn from_stored(text: &str) -> Option<Self> {
+        match text {
+            "in_stock" => Some(Self::InStock),
+            "out_of_stock" => Some(Self::OutOfStock),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Availability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InStock => f.write_str("in stock"),
+            Self::OutOfStock => f.write_str("out of stock"),
+        }
+    }
+}
+
+/// Stores `availability` of `product_id` when it differs from the last stored one, so the
+/// table holds when each product went out of and back in stock.
+pub async fn record_availability(product_id: i64, availability: Availability) {
+    if latest_availability(product_id).await == Some(availability) {
+        return;
+    }
+
+    let db = db::get_db().await;
+    let insert_result =
+        sqlx::query("INSERT INTO stock_events (product_id, availability) VALUES (?, ?)")
+            .bind(product_id)
+            .bind(availability.as_str())
+            .execute(&db)
+            .await;
+
+    if let Err(err) = insert_result {
+        log::error!(
+            "Insert of availability for product {} failed! {:?}",
+            product_id,
+            err
+        );
+    }
+}
+
+pub async fn latest_availability(product_id: i64) -> Option<Availability> {
+    let db = db::get_db().await;
+    let availability_query = sqlx::query_scalar::<_, String>(
+        "SELECT availability FROM stock_events WHERE product_id = ?
+         ORDER BY timestamp DESC, id DESC LIMIT 1",
+    )
+    .bind(product_id)
+    .fetch_optional(&db)
+    .await;
+
+    match availability_query {
+        Ok(availability) => availability.as_deref().and_then(Availability::from_stored),
+        Err(err) => {
+            log::error!(
+                "Failed to query availability of product {}! {:?}",
+                product_id,
+                err
+            );
+            None
+        }
+    }
+}
+
+/// Message announcing that `name` went out of or back in stock, `snapshot` being the price
+/// found by the check that saw it back.
+pub fn change_message(
+    name: &str,
+    previous: Availability,
+    current: Availability,
+    snapshot: Option<&PriceSnapshot>,
+) -> Option<String> {
+    match (previous, current) {
+        (Availability::OutOfStock, Availability::InStock) => Some(match snapshot {
+            Some(snapshot) => format!("✅ {} is back in stock at {}! 🛒", name, snapshot),
+            None => format!("✅ {} is back in stock! 🛒", name),
+        }),
+        (Availability::InStock, Availability::OutOfStock) => {
+            Some(format!("❌ {} is out of stock.", name))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+
+    #[test]
+    fn test_parse_availability() {
+        assert_eq!(
+            Availability::parse("https://schema.org/InStock"),
+            Some(Availability::InStock)
+        );
+        assert_eq!(
+            Availability::parse("http://schema.org/OutOfStock"),
+            Some(Availability::OutOfStock)
+        );
+        assert_eq!(
+            Availability::parse("SoldOut"),
+            Some(Availability::OutOfStock)
+        );
+        assert_eq!(Availability::parse("oos"), Some(Availability::OutOfStock));
+        assert_eq!(Availability::parse("in stock"), Some(Availability::InStock));
+        assert_eq!(Availability::parse("PreOrder"), None);
+    }
+
+    #[test]
+    fn test_change_message() {
+        let snapshot = PriceSnapshot::new(Money::eur(129));
+        assert_eq!(
+            change_message(
+                "milk",
+                Availability::OutOfStock,
+                Availability::InStock,
+                Some(&snapshot)
+            )
+            .as_deref(),
+            Some("✅ milk is back in stock at 1,29 €! 🛒")
+        );
+        assert_eq!(
+            change_message(
+                "milk",
+                Availability::InStock,
+                Availability::OutOfStock,
+                None
+            )
+            .as_deref(),
+            Some("❌ milk is out of stock.")
+        );
+        assert_eq!(
+            change_message("milk", Availability::InStock, Availability::InStock, None),
+            None
+        );
+    }
+}
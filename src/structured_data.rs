@@ -0,0 +1,256 @@
+use crate::money::{Currency, Money};
+use crate::stock::Availability;
+use scraper::{Html, Selector};
+use serde_json::Value;
+
+/// Extracts the price from structured data embedded in the page, trying JSON-LD, then
+/// microdata, then OpenGraph product tags.
+pub fn extract_price(document: &Html) -> Option<Money> {
+    json_ld_price(document)
+        .or_else(|| microdata_price(document))
+        .or_else(|| open_graph_price(document))
+}
+
+/// Extracts the availability from structured data, trying JSON-LD, then microdata, then
+/// OpenGraph product tags like [`extract_price`].
+pub fn extract_availability(document: &Html) -> Option<Availability> {
+    json_ld_offer(document, &offer_availability)
+        .or_else(|| {
+            itemprop_values(document, "availability").find_map(|text| Availability::parse(&text))
+        })
+        .or_else(|| {
+            let selector = Selector::parse(r#"meta[property="product:availability"]"#).ok()?;
+            document
+                .select(&selector)
+                .find_map(|element| Availability::parse(element.value().attr("content")?))
+        })
+}
+
+/// Reads the first `schema.org/Product` offer price from `application/ld+json` scripts, in its
+/// `priceCurrency`, else in euros.
+pub fn json_ld_price(document: &Html) -> Option<Money> {
+    json_ld_offer(document, &offer_price)
+}
+
+fn json_ld_offer<T>(document: &Html, read_offer: &impl Fn(&Value) -> Option<T>) -> Option<T> {
+    let selector = Selector::parse(r#"script[type="application/ld+json"]"#).ok()?;
+    document.select(&selector).find_map(|element| {
+        let json = element.text().collect::<String>();
+        let value: Value = serde_json::from_str(&json).ok()?;
+        find_product_offer(&value, read_offer)
+    })
+}
+
+/// Reads `itemprop="price"` markup, preferring the `content` attribute over the element text,
+/// in the `itemprop="priceCurrency"` currency, else in euros.
+pub fn microdata_price(document: &Html) -> Option<Money> {
+    let currency = itemprop_values(document, "priceCurrency")
+        .find_map(|code| Currency::parse(&code))
+        .unwrap_or(Currency::EUR);
+    itemprop_values(document, "price").find_map(|text| Money::parse(&text, currency))
+}
+
+/// Values of the `itemprop` elements named `name`: their `content` or `href` attribute, else
+/// their text.
+fn itemprop_values<'a>(document: &'a Html, name: &str) -> impl Iterator<Item = String> + 'a {
+    let selector = Selector::parse(&format!(r#"[itemprop="{}"]"#, name)).ok();
+    selector
+        .into_iter()
+        .flat_map(move |selector| document.select(&selector).collect::<Vec<_>>())
+        .map(|element| {
+            let value = element.value();
+            match value.attr("content").or_else(|| value.attr("href")) {
+                Some(attribute) => attribute.to_string(),
+                None => element.text().collect::<String>(),
+            }
+        })
+}
+
+/// Reads the `product:price:amount` OpenGraph meta tag, in the `product:price:currency`
+/// currency, else in euros.
+pub fn open_graph_price(document: &Html) -> Option<Money> {
+    let meta = |property: &str| {
+        let selector = Selector::parse(&format!(r#"meta[property="{}"]"#, property)).ok()?;
+        document
+            .select(&selector)
+            .find_map(|element| element.value().attr("content").map(str::to_string))
+    };
+    let currency = meta("product:price:currency")
+        .and_then(|code| Currency::parse(&code))
+        .unwrap_or(Currency::EUR);
+    Money::parse(&meta("product:price:amount")?, currency)
+}
+
+fn find_product_offer<T>(value: &Value, read_offer: &impl Fn(&Value) -> Option<T>) -> Option<T> {
+    match value {
+        Value::Array(values) => values
+            .iter()
+            .find_map(|value| find_product_offer(value, read_offer)),
+        Value::Object(object) => {
+            if let Some(graph) = object.get("@graph") {
+                return find_product_offer(graph, read_offer);
+            }
+            if !has_type(value, "Product") {
+                return None;
+            }
+            object.get("offers").and_then(read_offer)
+        }
+        _ => None,
+    }
+}
+
+fn offer_availability(offers: &Value) -> Option<Availability> {
+    match offers {
+        Value::Array(offers) => offers.iter().find_map(offer_availability),
+        Value::Object(offer) => offer
+            .get("availability")
+            .and_then(Value::as_str)
+            .and_then(Availability::parse),
+        _ => None,
+    }
+}
+
+fn offer_price(offers: &Value) -> Option<Money> {
+    match offers {
+        Value::Array(offers) => offers.iter().find_map(offer_price),
+        Value::Object(offer) => {
+            let currency = offer
+                .get("priceCurrency")
+                .and_then(Value::as_str)
+                .and_then(Currency::parse)
+                .unwrap_or(Currency::EUR);
+            ["price", "lowPrice"].iter().find_map(|key| {
+                offer
+                    .get(*key)
+                    .and_then(|value| json_price(value, currency))
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Numbers are parsed from their JSON text, so `1.29` is exactly 129 cents.
+fn json_price(value: &Value, currency: Currency) -> Option<Money> {
+    match value {
+        Value::Number(number) => Money::parse_with_decimal(&number.to_string(), '.', currency),
+        Value::String(text) => Money::parse(text, currency),
+        _ => None,
+    }
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match value.get("@type") {
+        Some(Value::String(kind)) => kind == expected,
+        Some(Value::Array(kinds)) => kinds.iter().any(|kind| kind == expected),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_ld_price() {
+        let document = Html::parse_document(
+            r#"<script type="application/ld+json">
+                {"@context": "https://schema.org", "@graph": [
+                    {"@type": "BreadcrumbList"},
+                    {"@type": "Product", "name": "Leite", "offers": {
+                        "@type": "Offer", "price": "1.29", "priceCurrency": "EUR"
+                    }}
+                ]}
+            </script>"#,
+        );
+        assert_eq!(json_ld_price(&document), Some(Money::eur(129)));
+    }
+
+    #[test]
+    fn test_json_ld_aggregate_offer() {
+        let document = Html::parse_document(
+            r#"<script type="application/ld+json">
+                [{"@type": ["Product"], "offers": [{"@type": "AggregateOffer", "lowPrice": 0.99}]}]
+            </script>"#,
+        );
+        assert_eq!(json_ld_price(&document), Some(Money::eur(99)));
+    }
+
+    #[test]
+    fn test_microdata_price() {
+        let document = Html::parse_document(
+            r#"<div itemscope itemtype="https://schema.org/Product">
+                <span itemprop="price" content="2.49">2,49 €</span>
+            </div>"#,
+        );
+        assert_eq!(microdata_price(&document), Some(Money::eur(249)));
+    }
+
+    #[test]
+    fn test_open_graph_price() {
+        let document =
+            Html::parse_document(r#"<meta property="product:price:amount" content="3,10">"#);
+        assert_eq!(open_graph_price(&document), Some(Money::eur(310)));
+    }
+
+    #[test]
+    fn test_price_currency() {
+        let usd = Currency::parse("USD").unwrap();
+        let document = Html::parse_document(
+            r#"<script type="application/ld+json">
+                {"@type": "Product", "offers": {"price": 2.5, "priceCurrency": "USD"}}
+            </script>"#,
+        );
+        assert_eq!(json_ld_price(&document), Some(Money::new(250, usd)));
+
+        let document = Html::parse_document(
+            r#"<meta property="product:price:amount" content="2.50">
+            <meta property="product:price:currency" content="USD">"#,
+        );
+        assert_eq!(open_graph_price(&document), Some(Money::new(250, usd)));
+    }
+
+    #[test]
+    fn test_availability() {
+        let document = Html::parse_document(
+            r#"<script type="application/ld+json">
+                {"@type": "Product", "offers": {
+                    "price": 1.29, "availability": "https://schema.org/OutOfStock"
+                }}
+            </script>"#,
+        );
+        assert_eq!(
+            extract_availability(&document),
+            Some(Availability::OutOfStock)
+        );
+
+        let document = Html::parse_document(
+            r#"<link itemprop="availability" href="https://schema.org/InStock">"#,
+        );
+        assert_eq!(extract_availability(&document), Some(Availability::InStock));
+
+        let document =
+            Html::parse_document(r#"<meta property="product:availability" content="oos">"#);
+        assert_eq!(
+            extract_availability(&document),
+            Some(Availability::OutOfStock)
+        );
+
+        let document = Html::parse_document("<span class=\"ct-price-formatted\">€1,29</span>");
+        assert_eq!(extract_availability(&document), None);
+    }
+
+    #[test]
+    fn test_extraction_order() {
+        let document = Html::parse_document(
+            r#"<meta property="product:price:amount" content="3.10">
+            <span itemprop="price" content="2.49"></span>
+            <script type="application/ld+json">
+                {"@type": "Product", "offers": {"price": 1.29}}
+            </script>"#,
+        );
+        assert_eq!(extract_price(&document), Some(Money::eur(129)));
+
+        let document = Html::parse_document("<span class=\"ct-price-formatted\">€1,29</span>");
+        assert_eq!(extract_price(&document), None);
+    }
+}
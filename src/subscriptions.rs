@@ -0,0 +1,89 @@
+use crate::db;
+use teloxide::types::ChatId;
+
+/// Subscribes `chat_id` to the service with `service_name`, returning whether the service
+/// exists. Subscribing twice is a no-op.
+pub async fn subscribe(chat_id: ChatId, service_name: &str) -> Result<bool, sqlx::Error> {
+    let db = db::get_db().await;
+    let insert_result = sqlx::query(
+        "INSERT OR IGNORE INTO subscriptions (chat_id, service_id)
+         SELECT ?, id FROM services WHERE name = ?",
+    )
+    .bind(chat_id.0)
+    .bind(service_name)
+    .execute(&db)
+    .await?;
+
+    if insert_result.rows_affected() > 0 {
+        return Ok(true);
+    }
+    let service_exists = sqlx::query("SELECT id FROM services WHERE name = ?")
+        .bind(service_name)
+        .fetch_optional(&db)
+        .await?;
+    Ok(service_exists.is_some())
+}
+
+/// Removes the subscription of `chat_id` to `service_name`, returning whether it existed.
+pub async fn unsubscribe(chat_id: ChatId, service_name: &str) -> Result<bool, sqlx::Error> {
+    let db = db::get_db().await;
+    let delete_result = sqlx::query(
+        "DELETE FROM subscriptions
+         WHERE chat_id = ? AND service_id IN (SELECT id FROM services WHERE name = ?)",
+    )
+    .bind(chat_id.0)
+    .bind(service_name)
+    .execute(&db)
+    .await?;
+    Ok(delete_result.rows_affected() > 0)
+}
+
+/// Names of the services `chat_id` is subscribed to.
+pub async fn get_subscriptions(chat_id: ChatId) -> Vec<String> {
+    let db = db::get_db().await;
+    let subscriptions_query = sqlx::query_scalar::<_, String>(
+        "SELECT services.name FROM subscriptions
+         JOIN services ON services.id = subscriptions.service_id
+         WHERE subscriptions.chat_id = ? ORDER BY services.name",
+    )
+    .bind(chat_id.0)
+    .fetch_all(&db)
+    .await;
+
+    match subscriptions_query {
+        Ok(names) => names,
+        Err(err) => {
+            log::error!(
+                "Failed to query subscriptions of chat {}! {:?}",
+                chat_id,
+                err
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// Chats subscribed to the service with `service_name`.
+pub async fn get_subscribers(service_name: &str) -> Vec<ChatId> {
+    let db = db::get_db().await;
+    let subscribers_query = sqlx::query_scalar::<_, i64>(
+        "SELECT subscriptions.chat_id FROM subscriptions
+         JOIN services ON services.id = subscriptions.service_id
+         WHERE services.name = ?",
+    )
+    .bind(service_name)
+    .fetch_all(&db)
+    .await;
+
+    match subscribers_query {
+        Ok(chat_ids) => chat_ids.into_iter().map(ChatId).collect(),
+        Err(err) => {
+            log::error!(
+                "Failed to query subscribers of service {}! {:?}",
+                service_name,
+                err
+            );
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use crate::chat;
+use crate::services::{BotService, ServiceContext};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::task::{JoinError, JoinHandle};
+use tokio::time::{sleep, Duration, Instant};
+
+/// Restarts allowed in a row before a service is marked as failed.
+const MAX_RESTARTS: u32 = 5;
+const BASE_RESTART_DELAY: Duration = Duration::from_secs(2);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(5 * 60);
+/// A run lasting at least this long is considered healthy and resets the restart count.
+const STABLE_RUN: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServiceState {
+    Stopped,
+    Running,
+    Restarting,
+    Failed,
+}
+
+impl fmt::Display for ServiceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            Self::Stopped => "stopped",
+            Self::Running => "running",
+            Self::Restarting => "restarting",
+            Self::Failed => "failed",
+        };
+        f.write_str(state)
+    }
+}
+
+/// Supervision record of a service, shared between [`crate::services::Service`] and its
+/// supervisor task.
+#[derive(Clone, Debug)]
+pub struct ServiceHealth {
+    pub state: ServiceState,
+    pub crashes: u32,
+    pub last_crash: Option<String>,
+}
+
+impl Default for ServiceHealth {
+    fn default() -> Self {
+        Self {
+            state: ServiceState::Stopped,
+            crashes: 0,
+            last_crash: None,
+        }
+    }
+}
+
+/// Aborts the wrapped task when dropped, so aborting the supervisor also stops the service.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Runs `service` and restarts it with exponential backoff whenever it panics or returns,
+/// until it has been restarted [`MAX_RESTARTS`] times in a row and is marked as failed.
+pub async fn supervise(
+    service: Arc<dyn BotService>,
+    ctx: ServiceContext,
+    health: Arc<Mutex<ServiceHealth>>,
+) {
+    let mut attempt: u32 = 0;
+    loop {
+        health.lock().unwrap().state = ServiceState::Running;
+        let started = Instant::now();
+        let mut task = AbortOnDrop(tokio::spawn(service.clone().run(ctx.clone())));
+        let reason = match (&mut task.0).await {
+            Ok(()) => "service returned".to_string(),
+            Err(err) if err.is_panic() => panic_message(err),
+            Err(_) => return,
+        };
+
+        if started.elapsed() >= STABLE_RUN {
+            attempt = 0;
+        }
+        attempt += 1;
+        log::error!(
+            "Service '{}' stopped unexpectedly ({}), attempt {} of {}",
+            &ctx.name,
+            reason,
+            attempt,
+            MAX_RESTARTS
+        );
+        {
+            let mut health = health.lock().unwrap();
+            health.crashes += 1;
+            health.last_crash = Some(reason.clone());
+            health.state = if attempt > MAX_RESTARTS {
+                ServiceState::Failed
+            } else {
+                ServiceState::Restarting
+            };
+        }
+
+        if attempt > MAX_RESTARTS {
+            chat::notify_admins(&format!(
+                "⚠️ Service '{}' failed after {} restarts, last error: {}",
+                &ctx.name, MAX_RESTARTS, reason
+            ))
+            .await;
+            return;
+        }
+        sleep(restart_delay(attempt, rand::random::<f64>())).await;
+    }
+}
+
+/// Doubles the delay on every attempt up to [`MAX_RESTART_DELAY`], then spreads it by up to
+/// ±50% according to `jitter` in `[0, 1)` so crashing services don't restart in lockstep.
+fn restart_delay(attempt: u32, jitter: f64) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16);
+    let delay = BASE_RESTART_DELAY
+        .saturating_mul(1 << exponent)
+        .min(MAX_RESTART_DELAY);
+    delay.mul_f64(0.5 + jitter)
+}
+
+fn panic_message(err: JoinError) -> String {
+    let panic = err.into_panic();
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        format!("panicked: {}", message)
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        format!("panicked: {}", message)
+    } else {
+        "panicked".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::BoxFuture;
+
+    struct PanickingService;
+
+    impl BotService for PanickingService {
+        fn name(&self) -> &str {
+            "panicking"
+        }
+
+        fn description(&self) -> String {
+            "Always panics".to_string()
+        }
+
+        fn run(self: Arc<Self>, _ctx: ServiceContext) -> BoxFuture<'static, ()> {
+            Box::pin(async { panic!("boom") })
+        }
+    }
+
+    #[test]
+    fn test_restart_delay_backoff() {
+        assert_eq!(restart_delay(1, 0.5), Duration::from_secs(2));
+        assert_eq!(restart_delay(2, 0.5), Duration::from_secs(4));
+        assert_eq!(restart_delay(4, 0.5), Duration::from_secs(16));
+        assert_eq!(restart_delay(30, 0.5), MAX_RESTART_DELAY);
+    }
+
+    #[test]
+    fn test_restart_delay_jitter() {
+        assert_eq!(restart_delay(2, 0.0), Duration::from_secs(2));
+        assert!(restart_delay(2, 0.999) < Duration::from_secs(6));
+    }
+
+    #[tokio::test]
+    async fn test_supervisor_records_crashes() {
+        let health = Arc::new(Mutex::new(ServiceHealth::default()));
+        let ctx = ServiceContext {
+            id: 1,
+            name: "panicking".to_string(),
+            default_schedule: None,
+            next_run: Arc::new(Mutex::new(None)),
+        };
+        let supervisor = tokio::spawn(supervise(Arc::new(PanickingService), ctx, health.clone()));
+
+        sleep(Duration::from_millis(100)).await;
+        let snapshot = health.lock().unwrap().clone();
+        assert_eq!(snapshot.state, ServiceState::Restarting);
+        assert_eq!(snapshot.crashes, 1);
+        assert_eq!(snapshot.last_crash.as_deref(), Some("panicked: boom"));
+        supervisor.abort();
+    }
+}
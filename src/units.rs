@@ -0,0 +1,175 @@
+use crate::money::Money;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::fmt;
+
+/// Matches pack sizes such as `emb. 1 lt`, `1,5 L`, `500 g` or `6 x 1 lt`.
+static PACK_SIZE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:(\d+)\s*x\s*)?(\d+(?:[.,]\d+)?)\s*([a-zA-Z]+)\b").unwrap());
+
+/// Base unit prices are normalized to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    Liter,
+    Kilogram,
+    Piece,
+}
+
+impl Unit {
+    /// Parses a unit as written by retailers, returning the base unit and how many of it one
+    /// of `text` is, e.g. `ml` is `(Liter, 0.001)`.
+    pub fn parse(text: &str) -> Option<(Self, f32)> {
+        let unit = match text.trim().trim_end_matches('.').to_lowercase().as_str() {
+            "l" | "lt" | "lts" | "litro" | "litros" => (Self::Liter, 1.0),
+            "dl" => (Self::Liter, 0.1),
+            "cl" => (Self::Liter, 0.01),
+            "ml" => (Self::Liter, 0.001),
+            "kg" | "kgs" => (Self::Kilogram, 1.0),
+            "g" | "gr" | "grs" => (Self::Kilogram, 0.001),
+            "un" | "uni" | "und" | "unid" | "unidade" | "unidades" => (Self::Piece, 1.0),
+            _ => return None,
+        };
+        Some(unit)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Liter => "L",
+            Self::Kilogram => "kg",
+            Self::Piece => "un",
+        }
+    }
+
+    /// Reads back the unit stored with [`Self::as_str`].
+    pub fn from_stored(text: &str) -> Option<Self> {
+        match text {
+            "L" => Some(Self::Liter),
+            "kg" => Some(Self::Kilogram),
+            "un" => Some(Self::Piece),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Contents of a pack, in its base unit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PackSize {
+    pub quantity: f32,
+    pub unit: Unit,
+}
+
+impl PackSize {
+    /// Parses the pack size shown on product pages, e.g. `emb. 6 x 1 lt` is 6 L.
+    pub fn parse(text: &str) -> Option<Self> {
+        PACK_SIZE.captures_iter(text).find_map(|captures| {
+            let (unit, factor) = Unit::parse(&captures[3])?;
+            let count = captures
+                .get(1)
+                .map_or(Some(1.0), |count| count.as_str().parse::<f32>().ok())?;
+            let amount = captures[2].replace(',', ".").parse::<f32>().ok()?;
+            let quantity = count * amount * factor;
+            (quantity > 0.0).then_some(Self { quantity, unit })
+        })
+    }
+
+    /// Price per base unit of a pack costing `price`.
+    pub fn unit_price(&self, price: Money) -> UnitPrice {
+        UnitPrice {
+            price: price.divide(self.quantity),
+            unit: self.unit,
+        }
+    }
+}
+
+impl fmt::Display for PackSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.quantity, self.unit)
+    }
+}
+
+/// Price per litre, kilogram or piece, so packs of different sizes can be compared.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnitPrice {
+    pub price: Money,
+    pub unit: Unit,
+}
+
+impl UnitPrice {
+    /// Normalizes a price shown per `unit`, e.g. `0,45 €` per `100g` is 4,50 €/kg.
+    pub fn parse_per(price: Money, unit: &str) -> Option<Self> {
+        let unit = unit.trim().trim_start_matches('/').trim();
+        let split = unit
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(unit.len());
+        let amount = match &unit[..split] {
+            "" => 1.0,
+            amount => amount.parse::<f32>().ok()?,
+        };
+        let (unit, factor) = Unit::parse(&unit[split..])?;
+        Some(Self {
+            price: price.divide(amount * factor),
+            unit,
+        })
+    }
+}
+
+impl fmt::Display for UnitPrice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.price, self.unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pack_size() {
+        let liter = |quantity| {
+            Some(PackSize {
+                quantity,
+                unit: Unit::Liter,
+            })
+        };
+        assert_eq!(PackSize::parse("emb. 1 lt"), liter(1.0));
+        assert_eq!(PackSize::parse("1,5 L"), liter(1.5));
+        assert_eq!(PackSize::parse("emb. 6 x 1 lt"), liter(6.0));
+        assert_eq!(PackSize::parse("emb. 200 ml"), liter(0.2));
+        assert_eq!(
+            PackSize::parse("500 g"),
+            Some(PackSize {
+                quantity: 0.5,
+                unit: Unit::Kilogram
+            })
+        );
+        assert_eq!(PackSize::parse("emb."), None);
+        assert_eq!(PackSize::parse("3 zz"), None);
+    }
+
+    #[test]
+    fn test_unit_price_from_pack() {
+        let pack = PackSize::parse("6 x 1 lt").unwrap();
+        let unit_price = pack.unit_price(Money::eur(594));
+        assert_eq!(unit_price.price, Money::eur(99));
+        assert_eq!(unit_price.to_string(), "0,99 €/L");
+    }
+
+    #[test]
+    fn test_parse_unit_price_per() {
+        let per_liter = UnitPrice::parse_per(Money::eur(99), "/lt").unwrap();
+        assert_eq!(per_liter.unit, Unit::Liter);
+        assert_eq!(per_liter.price, Money::eur(99));
+
+        let per_kilogram = UnitPrice::parse_per(Money::eur(45), "/100g").unwrap();
+        assert_eq!(per_kilogram.unit, Unit::Kilogram);
+        assert_eq!(per_kilogram.price, Money::eur(450));
+
+        assert!(UnitPrice::parse_per(Money::eur(100), "/caixa").is_none());
+    }
+}
@@ -0,0 +1,127 @@
+use crate::db;
+use crate::money::Currency;
+use std::fmt;
+use teloxide::types::UserId;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Admin,
+    User,
+    Blocked,
+}
+
+impl Role {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "admin" => Some(Self::Admin),
+            "user" => Some(Self::User),
+            "blocked" => Some(Self::Blocked),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::User => "user",
+            Self::Blocked => "blocked",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Role of `user_id`, `None` for users that were never granted access.
+pub async fn get_role(user_id: UserId) -> Option<Role> {
+    let db = db::get_db().await;
+    let role_query = sqlx::query_scalar::<_, String>("SELECT role FROM users WHERE user_id = ?")
+        .bind(user_id.0 as i64)
+        .fetch_optional(&db)
+        .await;
+
+    match role_query {
+        Ok(role) => role.and_then(|role| Role::parse(&role)),
+        Err(err) => {
+            log::error!("Failed to query role of user {}! {:?}", user_id, err);
+            None
+        }
+    }
+}
+
+pub async fn set_role(user_id: UserId, role: Role) -> Result<(), sqlx::Error> {
+    let db = db::get_db().await;
+    sqlx::query(
+        "INSERT INTO users (user_id, role) VALUES (?, ?)
+         ON CONFLICT (user_id) DO UPDATE SET role = excluded.role",
+    )
+    .bind(user_id.0 as i64)
+    .bind(role.as_str())
+    .execute(&db)
+    .await?;
+
+    log::info!("User {} now has role '{}'", user_id, role);
+    Ok(())
+}
+
+/// Currency `user_id` wants prices shown in, EUR until they pick another with `/currency`.
+pub async fn get_currency(user_id: UserId) -> Currency {
+    let db = db::get_db().await;
+    let currency_query =
+        sqlx::query_scalar::<_, Option<String>>("SELECT currency FROM users WHERE user_id = ?")
+            .bind(user_id.0 as i64)
+            .fetch_optional(&db)
+            .await;
+
+    match currency_query {
+        Ok(currency) => currency
+            .flatten()
+            .and_then(|code| Currency::parse(&code))
+            .unwrap_or(Currency::EUR),
+        Err(err) => {
+            log::error!("Failed to query currency of user {}! {:?}", user_id, err);
+            Currency::EUR
+        }
+    }
+}
+
+/// Stores the preferred currency of `user_id`, returning whether the user exists.
+pub async fn set_currency(user_id: UserId, currency: Currency) -> Result<bool, sqlx::Error> {
+    let db = db::get_db().await;
+    let update_result = sqlx::query("UPDATE users SET currency = ? WHERE user_id = ?")
+        .bind(currency.as_str())
+        .bind(user_id.0 as i64)
+        .execute(&db)
+        .await?;
+
+    Ok(update_result.rows_affected() > 0)
+}
+
+/// Makes sure the configured admin can always reach the bot, whatever the table says.
+pub async fn bootstrap_admin(user_id: UserId) {
+    if let Err(err) = set_role(user_id, Role::Admin).await {
+        log::error!("Failed to bootstrap admin {}! {:?}", user_id, err);
+    }
+}
+
+pub async fn get_admins() -> Vec<UserId> {
+    let db = db::get_db().await;
+    let admins_query = sqlx::query_scalar::<_, i64>("SELECT user_id FROM users WHERE role = ?")
+        .bind(Role::Admin.as_str())
+        .fetch_all(&db)
+        .await;
+
+    match admins_query {
+        Ok(user_ids) => user_ids
+            .into_iter()
+            .map(|user_id| UserId(user_id as u64))
+            .collect(),
+        Err(err) => {
+            log::error!("Failed to query admins! {:?}", err);
+            Vec::new()
+        }
+    }
+}